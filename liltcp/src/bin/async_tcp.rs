@@ -20,6 +20,7 @@ use stm32h7xx_hal::gpio::{ErasedPin, Output};
 use stm32h7xx_hal::{
     ethernet::{self, PHY as _},
     interrupt, pac,
+    rng::{RngCore, RngExt},
 };
 
 #[cortex_m_rt::entry]
@@ -59,7 +60,11 @@ fn main() -> ! {
 
     lilos::time::initialize_sys_tick(&mut cp.SYST, ccdr.clocks.sysclk().to_Hz());
 
-    let config = smoltcp::iface::Config::new(liltcp::MAC.into());
+    let mut rng = dp.RNG.constrain(ccdr.peripheral.RNG, &ccdr.clocks);
+    let random_seed: u64 = rng.gen().unwrap();
+
+    let mut config = smoltcp::iface::Config::new(liltcp::MAC.into());
+    config.random_seed = random_seed;
     let mut interface = Interface::new(config, &mut eth_dma, liltcp::smoltcp_lilos::smol_now());
     interface.update_ip_addrs(|addrs| {
         let _ = addrs.push(IpCidr::new(
@@ -72,7 +77,7 @@ fn main() -> ! {
     let mut storage = [SocketStorage::EMPTY; 1];
     // NOTE: This unnecessarily exposes implementation details of the Stack's shared state.
     // For the purposes of this demo, it is fine, but it should be hidden in production impl.
-    let inner_stack = RefCell::new(InnerStack::new(&mut storage, interface));
+    let inner_stack = RefCell::new(InnerStack::new(&mut storage, interface, random_seed));
     let stack = Stack::new(&inner_stack);
     // ANCHOR_END: stack_init
 
@@ -98,12 +103,10 @@ async fn tcp_client_task(stack: Stack<'_>) -> Infallible {
     static mut TX: [u8; 1024] = [0u8; 1024];
     static mut RX: [u8; 1024] = [0u8; 1024];
 
-    let mut client = TcpClient::new(stack, unsafe { &mut RX[..] }, unsafe { &mut TX[..] });
+    let client = TcpClient::new(stack, unsafe { &mut RX[..] }, unsafe { &mut TX[..] });
+    let mut client = defmt::unwrap!(client);
 
-    client
-        .connect(liltcp::REMOTE_ENDPOINT, liltcp::LOCAL_ENDPOINT)
-        .await
-        .unwrap();
+    defmt::unwrap!(client.connect(liltcp::REMOTE_ENDPOINT).await);
 
     defmt::info!("Connected.");
 
@@ -138,20 +141,23 @@ async fn net_task(
                 .unwrap_or(Duration::from_millis(1))
         });
 
-        match embassy_futures::select::select(
+        match embassy_futures::select::select3(
             lilos::time::sleep_for(lilos::time::Millis(poll_delay.millis())),
             IRQ_NOTIFY.until_next(),
+            stack.until_runner_woken(),
         )
         .await
         {
-            select::Either::First(_) => {}
-            select::Either::Second(_) => {}
+            select::Either3::First(_) => {}
+            select::Either3::Second(_) => {}
+            select::Either3::Third(_) => {}
         }
 
         let eth_last = eth_up;
         eth_up = phy.poll_link();
 
         link_led.set_state(eth_up.into());
+        stack.set_link_up(eth_up);
 
         if eth_up != eth_last {
             if eth_up {
@@ -164,7 +170,7 @@ async fn net_task(
             continue;
         }
 
-        stack.with(|(sockets, interface)| interface.poll(smol_now(), &mut dev, sockets));
+        stack.poll(&mut dev);
     }
 }
 // ANCHOR_END: net_task
@@ -0,0 +1,108 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use embassy_futures::select::{select3, Either3};
+use lilos::time::Millis;
+use liltcp::demo;
+use liltcp::dhcp::{DhcpClient, Ipv4Config};
+use liltcp::mdns::{MdnsResponder, Service};
+use liltcp::stack::{InnerStack, Stack};
+use liltcp::tcp::TcpListener;
+use liltcp::udp;
+
+use smoltcp::iface::SocketStorage;
+use smoltcp::wire::Ipv4Cidr;
+use stm32h7xx_hal::interrupt;
+
+/// The service advertised by the responder, served by the `echo_task`.
+const ECHO: Service = Service {
+    instance: "liltcp echo",
+    service: "_echo._tcp",
+    port: 7,
+};
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let board = demo::init();
+
+    // DHCP, mDNS and echo sockets
+    let mut storage = [SocketStorage::EMPTY; 3];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, async {
+        match select3(dhcp_task(stack), mdns_task(stack), echo_task(stack)).await {
+            Either3::First(never) | Either3::Second(never) | Either3::Third(never) => never,
+        }
+    })
+}
+
+async fn dhcp_task(stack: Stack<'_>) -> Infallible {
+    let fallback = Ipv4Config {
+        address: Ipv4Cidr::new(liltcp::IP_ADDR, liltcp::PREFIX_LEN),
+        gateway: None,
+        dns_servers: heapless::Vec::new(),
+    };
+
+    let client = defmt::unwrap!(DhcpClient::new(stack));
+    let mut client = client.with_fallback(fallback, Millis(10_000));
+    client.run().await
+}
+
+async fn mdns_task(stack: Stack<'_>) -> Infallible {
+    static mut RX_META: [udp::PacketMetadata; 2] = [udp::PacketMetadata::EMPTY; 2];
+    static mut RX: [u8; 1024] = [0u8; 1024];
+    static mut TX_META: [udp::PacketMetadata; 2] = [udp::PacketMetadata::EMPTY; 2];
+    static mut TX: [u8; 1024] = [0u8; 1024];
+
+    // e.g. `avahi-browse -r _echo._tcp` finds the service
+    let responder = unsafe {
+        MdnsResponder::new(
+            stack,
+            "liltcp",
+            &[ECHO],
+            &mut RX_META[..],
+            &mut RX[..],
+            &mut TX_META[..],
+            &mut TX[..],
+        )
+    };
+    defmt::unwrap!(responder).run().await
+}
+
+/// Sends the received data back, one client at a time.
+async fn echo_task(stack: Stack<'_>) -> Infallible {
+    static mut RX: [u8; 512] = [0u8; 512];
+    static mut TX: [u8; 512] = [0u8; 512];
+
+    let listener = TcpListener::new(
+        stack,
+        ECHO.port,
+        [unsafe { &mut RX[..] }],
+        [unsafe { &mut TX[..] }],
+    );
+    let listener = defmt::unwrap!(listener);
+
+    loop {
+        let mut connection = listener.accept().await;
+
+        let mut buffer = [0u8; 64];
+        while let Ok(len @ 1..) = connection.recv(&mut buffer).await {
+            if connection.write_all(&buffer[..len]).await.is_err() {
+                break;
+            }
+        }
+        let _ = connection.close().await;
+    }
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
@@ -4,7 +4,7 @@
 use core::convert::Infallible;
 
 use lilos::exec::Interrupts;
-use liltcp as _;
+use liltcp::stack::{next_ephemeral_port, seeded_ephemeral_port};
 use smoltcp::{
     iface::{Interface, SocketSet, SocketStorage},
     storage::RingBuffer,
@@ -14,6 +14,7 @@ use stm32h7xx_hal::{
     ethernet::{self, phy::LAN8742A, StationManagement, PHY as _},
     gpio::{ErasedPin, Output},
     interrupt, pac,
+    rng::{RngCore, RngExt},
 };
 
 #[cortex_m_rt::entry]
@@ -53,8 +54,12 @@ fn main() -> ! {
 
     lilos::time::initialize_sys_tick(&mut cp.SYST, ccdr.clocks.sysclk().to_Hz());
 
+    let mut rng = dp.RNG.constrain(ccdr.peripheral.RNG, &ccdr.clocks);
+    let random_seed: u64 = rng.gen().unwrap();
+
     // ANCHOR: interface_init
-    let config = smoltcp::iface::Config::new(liltcp::MAC.into());
+    let mut config = smoltcp::iface::Config::new(liltcp::MAC.into());
+    config.random_seed = random_seed;
     let mut interface = Interface::new(config, &mut eth_dma, liltcp::smoltcp_lilos::smol_now());
     interface.update_ip_addrs(|addrs| {
         let _ = addrs.push(IpCidr::new(
@@ -79,7 +84,8 @@ fn main() -> ! {
                     eth_dma,
                     &mut sockets,
                     lan8742a,
-                    gpio.link_led
+                    gpio.link_led,
+                    random_seed
                 )),
             ],
             lilos::exec::ALL_TASKS,
@@ -96,6 +102,7 @@ async fn net_task(
     sockets: &mut SocketSet<'_>,
     mut phy: LAN8742A<impl StationManagement>,
     mut link_led: ErasedPin<Output>,
+    random_seed: u64,
 ) -> Infallible {
     static mut RX: [u8; 1024] = [0u8; 1024];
     static mut TX: [u8; 1024] = [0u8; 1024];
@@ -106,6 +113,7 @@ async fn net_task(
     let client = smoltcp::socket::tcp::Socket::new(rx_buffer, tx_buffer);
 
     let handle = sockets.add(client);
+    let mut local_port = seeded_ephemeral_port(random_seed);
 
     let mut eth_up = false;
 
@@ -136,10 +144,12 @@ async fn net_task(
             let socket = sockets.get_mut::<smoltcp::socket::tcp::Socket>(handle);
             if !socket.is_open() {
                 defmt::info!("not open, issuing connect");
+                // the previous connection may still be in TIME-WAIT on the server
+                local_port = next_ephemeral_port(local_port);
                 defmt::unwrap!(socket.connect(
                     interface.context(),
                     liltcp::REMOTE_ENDPOINT,
-                    liltcp::LOCAL_ENDPOINT,
+                    local_port,
                 ));
 
                 break 'worker;
@@ -0,0 +1,65 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use liltcp::demo;
+use liltcp::raw_ethernet::{self, EthernetSocket};
+use liltcp::stack::{InnerStack, Stack};
+
+use smoltcp::iface::SocketStorage;
+use stm32h7xx_hal::interrupt;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let mut board = demo::init();
+    board.set_static_address();
+
+    // the raw Ethernet sockets live outside of the socket set
+    let mut storage: [SocketStorage; 0] = [];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, raw_ethernet_task(stack))
+}
+
+/// Echoes the frames of the IEEE 802 local experimental EtherType back to their sender.
+async fn raw_ethernet_task(stack: Stack<'_>) -> Infallible {
+    const ETHERTYPE: u16 = 0x88b5;
+
+    static mut RX_META: [raw_ethernet::PacketMetadata; 4] =
+        [raw_ethernet::PacketMetadata::EMPTY; 4];
+    static mut RX: [u8; 2048] = [0u8; 2048];
+    static mut TX_META: [raw_ethernet::PacketMetadata; 4] =
+        [raw_ethernet::PacketMetadata::EMPTY; 4];
+    static mut TX: [u8; 2048] = [0u8; 2048];
+
+    let socket = unsafe {
+        EthernetSocket::new(
+            stack,
+            ETHERTYPE,
+            &mut RX_META[..],
+            &mut RX[..],
+            &mut TX_META[..],
+            &mut TX[..],
+        )
+    };
+    let mut socket = defmt::unwrap!(socket);
+
+    let mut buffer = [0u8; 1500];
+    loop {
+        let Ok((len, source)) = socket.recv(&mut buffer).await else {
+            continue;
+        };
+        let _ = socket.send(source, &buffer[..len]).await;
+    }
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
@@ -0,0 +1,63 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use embassy_futures::select::{select, Either};
+use liltcp::demo;
+use liltcp::reconnect::{Backoff, ReconnectingClient};
+use liltcp::stack::{InnerStack, Stack};
+use liltcp::tcp::TcpClient;
+
+use smoltcp::iface::SocketStorage;
+use stm32h7xx_hal::interrupt;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let mut board = demo::init();
+    board.set_static_address();
+
+    let mut storage = [SocketStorage::EMPTY; 1];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, reconnecting_task(stack))
+}
+
+// ANCHOR: reconnecting_task
+async fn reconnecting_task(stack: Stack<'_>) -> Infallible {
+    static mut TX: [u8; 1024] = [0u8; 1024];
+    static mut RX: [u8; 1024] = [0u8; 1024];
+
+    let client = TcpClient::new(stack, unsafe { &mut RX[..] }, unsafe { &mut TX[..] });
+    let client = ReconnectingClient::new(
+        defmt::unwrap!(client),
+        liltcp::REMOTE_ENDPOINT,
+        Backoff::default(),
+    );
+
+    let loopback = async {
+        loop {
+            let mut buffer = [0u8; 5];
+            // errors are handled by reconnecting in `client.run()`
+            let Ok(len) = client.recv(&mut buffer).await else {
+                continue;
+            };
+            let _ = client.write_all(&buffer[..len]).await;
+        }
+    };
+
+    match select(client.run(), loopback).await {
+        Either::First(never) | Either::Second(never) => never,
+    }
+}
+// ANCHOR_END: reconnecting_task
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
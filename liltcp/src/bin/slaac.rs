@@ -0,0 +1,49 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use liltcp::demo;
+use liltcp::slaac::{PacketMetadata, Slaac};
+use liltcp::stack::{InnerStack, Stack};
+
+use smoltcp::iface::SocketStorage;
+use stm32h7xx_hal::interrupt;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let board = demo::init();
+
+    let mut storage = [SocketStorage::EMPTY; 1];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, slaac_task(stack))
+}
+
+async fn slaac_task(stack: Stack<'_>) -> Infallible {
+    static mut RX_META: [PacketMetadata; 4] = [PacketMetadata::EMPTY; 4];
+    static mut RX: [u8; 512] = [0u8; 512];
+    static mut TX_META: [PacketMetadata; 1] = [PacketMetadata::EMPTY; 1];
+    static mut TX: [u8; 128] = [0u8; 128];
+
+    let slaac = unsafe {
+        Slaac::new(
+            stack,
+            &mut RX_META[..],
+            &mut RX[..],
+            &mut TX_META[..],
+            &mut TX[..],
+        )
+    };
+    defmt::unwrap!(slaac).run().await
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
@@ -0,0 +1,74 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use embassy_futures::select;
+use lilos::time::Millis;
+use liltcp::demo;
+use liltcp::dhcp::{DhcpClient, Ipv4Config};
+use liltcp::sntp::SntpClient;
+use liltcp::stack::{InnerStack, Stack};
+use liltcp::udp;
+
+use smoltcp::iface::SocketStorage;
+use smoltcp::wire::Ipv4Cidr;
+use stm32h7xx_hal::interrupt;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let board = demo::init();
+
+    // DHCP, DNS and SNTP sockets
+    let mut storage = [SocketStorage::EMPTY; 3];
+    let mut dns_queries = [None, None];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let mut stack = Stack::new(&inner_stack);
+    defmt::unwrap!(stack.enable_dns(&mut dns_queries));
+
+    demo::run(board.network, stack, async {
+        match select::select(dhcp_task(stack), sntp_task(stack)).await {
+            select::Either::First(never) | select::Either::Second(never) => never,
+        }
+    })
+}
+
+async fn dhcp_task(stack: Stack<'_>) -> Infallible {
+    let fallback = Ipv4Config {
+        address: Ipv4Cidr::new(liltcp::IP_ADDR, liltcp::PREFIX_LEN),
+        gateway: None,
+        dns_servers: heapless::Vec::new(),
+    };
+
+    let client = defmt::unwrap!(DhcpClient::new(stack));
+    let mut client = client.with_fallback(fallback, Millis(10_000));
+    client.run().await
+}
+
+async fn sntp_task(stack: Stack<'_>) -> Infallible {
+    static mut RX_META: [udp::PacketMetadata; 1] = [udp::PacketMetadata::EMPTY; 1];
+    static mut RX: [u8; 64] = [0u8; 64];
+    static mut TX_META: [udp::PacketMetadata; 1] = [udp::PacketMetadata::EMPTY; 1];
+    static mut TX: [u8; 64] = [0u8; 64];
+
+    let client = unsafe {
+        SntpClient::new(
+            stack,
+            "pool.ntp.org",
+            &mut RX_META[..],
+            &mut RX[..],
+            &mut TX_META[..],
+            &mut TX[..],
+        )
+    };
+    defmt::unwrap!(client).run().await
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
@@ -0,0 +1,61 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use liltcp::demo;
+use liltcp::stack::{InnerStack, Stack};
+use liltcp::tcp::TcpListener;
+
+use smoltcp::iface::SocketStorage;
+use stm32h7xx_hal::interrupt;
+
+const PORT: u16 = 8002;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let mut board = demo::init();
+    board.set_static_address();
+
+    let mut storage = [SocketStorage::EMPTY; 1];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, greeter_task(stack))
+}
+
+/// Greets every client and resets the connection right after.
+///
+/// The peer has to see the RST sent on dropping the connection, and a single socket backlog
+/// has to get back to listening for the next client, see `test-tcp-server/tests/listener.rs`.
+async fn greeter_task(stack: Stack<'_>) -> Infallible {
+    static mut RX: [u8; 256] = [0u8; 256];
+    static mut TX: [u8; 256] = [0u8; 256];
+
+    let listener = TcpListener::new(
+        stack,
+        PORT,
+        [unsafe { &mut RX[..] }],
+        [unsafe { &mut TX[..] }],
+    );
+    let listener = defmt::unwrap!(listener);
+
+    loop {
+        let mut connection = listener.accept().await;
+        defmt::info!("Accepted {}", connection.remote_endpoint());
+
+        if connection.write_all(b"hello\n").await.is_ok() {
+            let _ = connection.flush().await;
+        }
+        // dropping the connection aborts it
+    }
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
@@ -0,0 +1,101 @@
+#![no_main]
+#![no_std]
+
+use core::{cell::RefCell, convert::Infallible};
+
+use lilos::time::Millis;
+use liltcp::demo;
+use liltcp::stack::{InnerStack, Stack};
+use liltcp::tcp::TcpClient;
+use liltcp::tls::{self, TlsClient};
+
+use smoltcp::iface::SocketStorage;
+use stm32h7xx_hal::interrupt;
+use stm32h7xx_hal::rng::Rng;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let mut board = demo::init();
+    board.set_static_address();
+
+    let mut storage = [SocketStorage::EMPTY; 1];
+    let inner_stack = RefCell::new(InnerStack::new(
+        &mut storage,
+        board.interface,
+        board.random_seed,
+    ));
+    let stack = Stack::new(&inner_stack);
+
+    demo::run(board.network, stack, tls_client_task(stack, board.rng))
+}
+
+/// Echoes the data received over a TLS session authenticated by a pre-shared key.
+///
+/// The server on the host can be started by:
+///
+/// ```text
+/// openssl s_server -tls1_3 -groups P-256 -accept 4433 -nocert -psk_identity liltcp \
+///     -psk 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f
+/// ```
+///
+/// The groups are limited, as embedded-tls fails to parse the hybrid post-quantum groups
+/// advertised by OpenSSL 3.5.
+///
+/// The `tls_echo` binary of the test-tcp-server checks the echo, `cargo run --bin tls_echo`.
+async fn tls_client_task(stack: Stack<'_>, mut rng: Rng) -> Infallible {
+    // NOTE: This is a demo key, the production key should be provisioned per device.
+    const PSK: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+        0x1e, 0x1f,
+    ];
+    const PSK_IDENTITY: &[u8] = b"liltcp";
+
+    static mut TX: [u8; 1024] = [0u8; 1024];
+    static mut RX: [u8; 1024] = [0u8; 1024];
+    static mut READ_RECORD: [u8; tls::MAX_RECORD_LEN] = [0u8; tls::MAX_RECORD_LEN];
+    static mut WRITE_RECORD: [u8; 1024] = [0u8; 1024];
+
+    let client = TcpClient::new(stack, unsafe { &mut RX[..] }, unsafe { &mut TX[..] });
+    let mut client = defmt::unwrap!(client);
+
+    loop {
+        if let Err(e) = client
+            .connect_with_timeout(liltcp::TLS_REMOTE_ENDPOINT, Millis(5_000))
+            .await
+        {
+            defmt::warn!("Connecting failed: {}", e);
+            client.abort();
+            lilos::time::sleep_for(Millis(1_000)).await;
+            continue;
+        }
+
+        // the record buffers are borrowed by a single session at a time,
+        // the previous one is closed before the next is created
+        let mut session: TlsClient =
+            TlsClient::new(client, unsafe { &mut READ_RECORD[..] }, unsafe {
+                &mut WRITE_RECORD[..]
+            });
+
+        if session.open_psk(&PSK, PSK_IDENTITY, &mut rng).await.is_ok() {
+            defmt::info!("TLS session established.");
+
+            let mut buffer = [0u8; 64];
+            while let Ok(len @ 1..) = session.recv(&mut buffer).await {
+                if session.write_all(&buffer[..len]).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        client = session.close().await;
+        // the socket may be left in TIME-WAIT
+        client.abort();
+        lilos::time::sleep_for(Millis(1_000)).await;
+    }
+}
+
+#[cortex_m_rt::interrupt]
+fn ETH() {
+    demo::on_eth_interrupt();
+}
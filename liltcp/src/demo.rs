@@ -0,0 +1,190 @@
+use core::{convert::Infallible, future::Future, pin::pin};
+
+use embassy_futures::select::{select3, Either3};
+use lilos::{
+    exec::{Interrupts, Notify},
+    time::{sleep_for, Millis},
+};
+use smoltcp::{iface::Interface, time::Duration, wire::IpCidr};
+use stm32h7xx_hal::{
+    ethernet::{self, phy::LAN8742A, PHY as _},
+    gpio::{ErasedPin, Output},
+    pac,
+    rng::{Rng, RngCore, RngExt},
+};
+
+use crate::{smoltcp_lilos::smol_now, stack::Stack};
+
+static IRQ_NOTIFY: Notify = Notify::new();
+
+pub struct Board {
+    /// The interface without any addresses, see [`Board::set_static_address`].
+    pub interface: Interface,
+    pub rng: Rng,
+    /// Seed of the interface, to be passed to the [`crate::stack::InnerStack`] as well.
+    pub random_seed: u64,
+    pub network: Network,
+}
+
+/// Peripherals used by the [`run`] tasks.
+pub struct Network {
+    nvic: pac::NVIC,
+    dma: ethernet::EthernetDMA<4, 4>,
+    phy: LAN8742A<ethernet::EthernetMAC>,
+    led: ErasedPin<Output>,
+    link_led: ErasedPin<Output>,
+}
+
+impl Board {
+    /// Assigns the [`crate::IP_ADDR`] to the interface, for the demos not using DHCP.
+    pub fn set_static_address(&mut self) {
+        self.interface.update_ip_addrs(|addrs| {
+            let _ = addrs.push(IpCidr::new(
+                crate::IP_ADDR.into_address(),
+                crate::PREFIX_LEN,
+            ));
+        });
+    }
+}
+
+/// Initializes the clocks, the Ethernet peripheral and the smoltcp interface.
+///
+/// This is the board setup of the `async_tcp` example, shared by the feature demos in `src/bin`.
+pub fn init() -> Board {
+    let mut cp = defmt::unwrap!(cortex_m::Peripherals::take());
+    let dp = defmt::unwrap!(pac::Peripherals::take());
+
+    let ccdr = crate::initialize_clock(dp.PWR, dp.RCC, &dp.SYSCFG);
+
+    let gpio = crate::init_gpio(
+        dp.GPIOA,
+        ccdr.peripheral.GPIOA,
+        dp.GPIOB,
+        ccdr.peripheral.GPIOB,
+        dp.GPIOC,
+        ccdr.peripheral.GPIOC,
+        dp.GPIOE,
+        ccdr.peripheral.GPIOE,
+        dp.GPIOG,
+        ccdr.peripheral.GPIOG,
+    );
+
+    let (mut dma, eth_mac) = ethernet::new(
+        dp.ETHERNET_MAC,
+        dp.ETHERNET_MTL,
+        dp.ETHERNET_DMA,
+        gpio.eth_pins,
+        unsafe { crate::take_des_ring() },
+        crate::MAC,
+        ccdr.peripheral.ETH1MAC,
+        &ccdr.clocks,
+    );
+
+    let mut phy = LAN8742A::new(eth_mac.set_phy_addr(0));
+    phy.phy_reset();
+    phy.phy_init();
+    crate::pass_all_multicast();
+
+    let mut rng = dp.RNG.constrain(ccdr.peripheral.RNG, &ccdr.clocks);
+    let random_seed: u64 = defmt::unwrap!(rng.gen().ok());
+
+    lilos::time::initialize_sys_tick(&mut cp.SYST, ccdr.clocks.sysclk().to_Hz());
+
+    let mut config = smoltcp::iface::Config::new(crate::MAC.into());
+    config.random_seed = random_seed;
+    let interface = Interface::new(config, &mut dma, smol_now());
+
+    Board {
+        interface,
+        rng,
+        random_seed,
+        network: Network {
+            nvic: cp.NVIC,
+            dma,
+            phy,
+            led: gpio.led,
+            link_led: gpio.link_led,
+        },
+    }
+}
+
+/// Runs the `demo` along with the LED and the network polling tasks.
+///
+/// The ETH interrupt handler of the demo has to call [`on_eth_interrupt`].
+pub fn run(network: Network, stack: Stack<'_>, demo: impl Future<Output = Infallible>) -> ! {
+    let Network {
+        mut nvic,
+        dma,
+        phy,
+        led,
+        link_led,
+    } = network;
+
+    unsafe {
+        crate::enable_eth_interrupt(&mut nvic);
+
+        lilos::exec::run_tasks_with_preemption(
+            &mut [
+                pin!(crate::led_task(led)),
+                pin!(demo),
+                pin!(net_task(stack, dma, phy, link_led)),
+            ],
+            lilos::exec::ALL_TASKS,
+            Interrupts::Filtered(crate::NVIC_BASEPRI),
+        )
+    }
+}
+
+pub fn on_eth_interrupt() {
+    unsafe {
+        ethernet::interrupt_handler();
+    }
+    IRQ_NOTIFY.notify();
+}
+
+/// Same as the `net_task` of the `async_tcp` example.
+async fn net_task(
+    mut stack: Stack<'_>,
+    mut dev: ethernet::EthernetDMA<4, 4>,
+    mut phy: LAN8742A<ethernet::EthernetMAC>,
+    mut link_led: ErasedPin<Output>,
+) -> Infallible {
+    let mut eth_up = false;
+
+    loop {
+        let poll_delay = stack.with(|(sockets, interface)| {
+            interface
+                .poll_delay(smol_now(), sockets)
+                .unwrap_or(Duration::from_millis(1))
+        });
+
+        match select3(
+            sleep_for(Millis(poll_delay.millis())),
+            IRQ_NOTIFY.until_next(),
+            stack.until_runner_woken(),
+        )
+        .await
+        {
+            Either3::First(_) | Either3::Second(_) | Either3::Third(_) => {}
+        }
+
+        let eth_last = eth_up;
+        eth_up = phy.poll_link();
+
+        link_led.set_state(eth_up.into());
+        stack.set_link_up(eth_up);
+
+        if eth_up != eth_last {
+            if eth_up {
+                defmt::info!("UP");
+            } else {
+                defmt::info!("DOWN");
+            }
+        }
+        if !eth_up {
+            continue;
+        }
+
+        stack.poll(&mut dev);
+    }
+}
@@ -0,0 +1,162 @@
+use core::{convert::Infallible, future::poll_fn, task::Poll};
+
+use lilos::time::{with_timeout, Millis};
+use smoltcp::{
+    iface::{Interface, SocketHandle},
+    socket::dhcpv4::{self, Event},
+    wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr},
+};
+
+use crate::{
+    stack::{Stack, MAX_DNS_SERVERS},
+    Error,
+};
+
+/// Network configuration, either leased from a DHCP server, or a static fallback.
+#[derive(Clone, Debug, PartialEq, Eq, defmt::Format)]
+pub struct Ipv4Config {
+    pub address: Ipv4Cidr,
+    pub gateway: Option<Ipv4Address>,
+    pub dns_servers: heapless::Vec<Ipv4Address, MAX_DNS_SERVERS>,
+}
+
+enum DhcpEvent {
+    Configured(Ipv4Config),
+    Deconfigured,
+}
+
+/// DHCPv4 client configuring the [`Stack`]'s interface.
+///
+/// Renewing the lease is handled by smoltcp itself, [`DhcpClient::run`] just applies
+/// the changes to the interface.
+pub struct DhcpClient<'a> {
+    stack: Stack<'a>,
+    handle: SocketHandle,
+    fallback: Option<Ipv4Config>,
+    fallback_after: Millis,
+}
+
+impl<'a> DhcpClient<'a> {
+    pub fn new(mut stack: Stack<'a>) -> Result<Self, Error> {
+        let handle = stack.add_socket(dhcpv4::Socket::new())?;
+
+        Ok(Self {
+            stack,
+            handle,
+            fallback: None,
+            fallback_after: Millis(0),
+        })
+    }
+
+    /// Applies `config` if no DHCP server answers within `after`.
+    ///
+    /// The client keeps trying to obtain a lease, which replaces the fallback configuration
+    /// once it is acquired.
+    pub fn with_fallback(mut self, config: Ipv4Config, after: Millis) -> Self {
+        self.fallback = Some(config);
+        self.fallback_after = after;
+        self
+    }
+
+    pub async fn run(&mut self) -> Infallible {
+        let mut leased = false;
+        let mut fallback_applied = false;
+
+        loop {
+            let event = match &self.fallback {
+                Some(_) if !leased && !fallback_applied => {
+                    with_timeout(self.fallback_after, self.next_event()).await
+                }
+                _ => Some(self.next_event().await),
+            };
+
+            match event {
+                Some(DhcpEvent::Configured(config)) => {
+                    defmt::info!("DHCP lease acquired: {}", config);
+                    self.apply(Some(&config));
+                    leased = true;
+                    fallback_applied = false;
+                }
+                Some(DhcpEvent::Deconfigured) => {
+                    defmt::warn!("DHCP lease lost");
+                    self.apply(None);
+                    leased = false;
+                }
+                None => {
+                    let config = self.fallback.clone();
+                    defmt::warn!("No DHCP server answered, using fallback: {}", config);
+                    self.apply(config.as_ref());
+                    fallback_applied = true;
+                }
+            }
+        }
+    }
+
+    async fn next_event(&mut self) -> DhcpEvent {
+        let handle = self.handle;
+        poll_fn(|cx| {
+            self.stack.with(|(sockets, _interface)| {
+                let socket = sockets.get_mut::<dhcpv4::Socket>(handle);
+                match socket.poll() {
+                    Some(Event::Configured(config)) => {
+                        Poll::Ready(DhcpEvent::Configured(Ipv4Config {
+                            address: config.address,
+                            gateway: config.router,
+                            dns_servers: config.dns_servers.iter().copied().collect(),
+                        }))
+                    }
+                    Some(Event::Deconfigured) => Poll::Ready(DhcpEvent::Deconfigured),
+                    None => {
+                        socket.register_waker(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    fn apply(&mut self, config: Option<&Ipv4Config>) {
+        self.stack
+            .with(|(_sockets, interface)| apply_config(interface, config));
+
+        let dns_servers = config
+            .map(|config| {
+                config
+                    .dns_servers
+                    .iter()
+                    .map(|server| IpAddress::Ipv4(*server))
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.stack.set_dns_servers(dns_servers);
+    }
+}
+
+fn apply_config(interface: &mut Interface, config: Option<&Ipv4Config>) {
+    interface.update_ip_addrs(|addrs| {
+        // keep the IPv6 addresses
+        addrs.retain(|addr| !matches!(addr, IpCidr::Ipv4(_)));
+        if let Some(config) = config {
+            // there is a space for at least one address
+            let _ = addrs.push(IpCidr::Ipv4(config.address));
+        }
+    });
+
+    match config.and_then(|config| config.gateway) {
+        Some(gateway) => {
+            // the routing table has a space for the default route
+            let _ = interface.routes_mut().add_default_ipv4_route(gateway);
+        }
+        None => {
+            interface.routes_mut().remove_default_ipv4_route();
+        }
+    }
+}
+
+impl Drop for DhcpClient<'_> {
+    fn drop(&mut self) {
+        self.stack
+            .with(|(sockets, _interface)| sockets.remove(self.handle));
+    }
+}
@@ -0,0 +1,428 @@
+use core::{future::poll_fn, task::Poll};
+
+use lilos::time::{Millis, TickTime};
+use smoltcp::{
+    iface::SocketHandle,
+    phy::{Device, DeviceCapabilities, RxToken},
+    socket::dns::{self, GetQueryResultError, QueryHandle},
+    time::Instant,
+    wire::{
+        DnsQueryType, EthernetFrame, EthernetProtocol, IpAddress, IpProtocol, Ipv4Packet,
+        Ipv6Packet, UdpPacket,
+    },
+};
+
+pub use smoltcp::socket::dns::DnsQuery;
+
+use crate::{stack::Stack, Error};
+
+/// The maximum number of addresses returned by [`Stack::resolve`].
+pub const MAX_ADDRESSES: usize = smoltcp::config::DNS_MAX_RESULT_COUNT;
+
+/// The number of names kept in the cache.
+pub const CACHE_SIZE: usize = 4;
+
+/// Names longer than this are resolved every time.
+const MAX_CACHED_NAME_LEN: usize = 64;
+
+/// The answers are cached for at most this long, regardless of their TTL.
+pub const MAX_CACHE_TTL: Millis = Millis(3_600_000);
+
+/// TTL of the answers whose TTL was not seen by the [`Snoop`],
+/// e.g. when the response was fragmented.
+const DEFAULT_CACHE_TTL: Millis = Millis(60_000);
+
+const DNS_PORT: u16 = 53;
+const HEADER_LEN: usize = 12;
+const FLAG_RESPONSE: u16 = 0x8000;
+const RCODE_MASK: u16 = 0x000f;
+
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_AAAA: u16 = 28;
+
+pub type Addresses = heapless::Vec<IpAddress, MAX_ADDRESSES>;
+type CachedName = heapless::String<MAX_CACHED_NAME_LEN>;
+
+struct CacheEntry {
+    name: CachedName,
+    addresses: Addresses,
+    expires_at: TickTime,
+}
+
+pub(crate) struct DnsState {
+    pub(crate) handle: Option<SocketHandle>,
+    cache: heapless::Vec<CacheEntry, CACHE_SIZE>,
+    /// TTLs of the latest responses, in seconds.
+    ttls: heapless::Deque<(CachedName, u32), CACHE_SIZE>,
+}
+
+impl DnsState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            handle: None,
+            cache: heapless::Vec::new(),
+            ttls: heapless::Deque::new(),
+        }
+    }
+
+    /// Records the TTL of the DNS response in the received `frame`, if it is one
+    /// and it comes from one of the `servers`.
+    fn snoop(&mut self, frame: &[u8], servers: &[IpAddress]) {
+        if self.handle.is_none() {
+            return;
+        }
+        let Some((source, response)) = udp_payload(frame, DNS_PORT) else {
+            return;
+        };
+        if !servers.contains(&source) {
+            return;
+        }
+        let Some(ttl) = response_ttl(response) else {
+            return;
+        };
+
+        if self.ttls.is_full() {
+            self.ttls.pop_front();
+        }
+        let _ = self.ttls.push_back(ttl);
+    }
+
+    /// Returns how long the answer for the `name` can be cached.
+    fn take_ttl(&mut self, name: &str) -> Millis {
+        let ttl = self
+            .ttls
+            .iter()
+            .rev()
+            .find(|(response_name, _ttl)| response_name.eq_ignore_ascii_case(name))
+            .map(|(_name, ttl)| Millis(u64::from(*ttl) * 1000))
+            .unwrap_or(DEFAULT_CACHE_TTL);
+
+        ttl.min(MAX_CACHE_TTL)
+    }
+
+    fn lookup(&mut self, name: &str, now: TickTime) -> Option<Addresses> {
+        self.cache.retain(|entry| entry.expires_at > now);
+        self.cache
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .map(|entry| entry.addresses.clone())
+    }
+
+    fn insert(&mut self, name: &str, addresses: &Addresses, now: TickTime) {
+        let ttl = self.take_ttl(name);
+        let Ok(name) = CachedName::try_from(name) else {
+            return;
+        };
+        if ttl == Millis(0) {
+            // RFC 1035, section 3.2.1, zero TTL answers must not be cached
+            return;
+        }
+
+        if self.cache.is_full() {
+            // evict the entry that would expire first
+            if let Some(oldest) = (0..self.cache.len()).min_by_key(|&i| self.cache[i].expires_at) {
+                self.cache.swap_remove(oldest);
+            }
+        }
+
+        let _ = self.cache.push(CacheEntry {
+            name,
+            addresses: addresses.clone(),
+            expires_at: now + ttl,
+        });
+    }
+}
+
+/// Device passing the received frames to the interface, while reading the TTLs of the DNS
+/// responses on the way.
+///
+/// smoltcp's DNS socket doesn't expose the TTLs, so the responses are read before
+/// the interface passes them to it. Only the responses of the servers set by
+/// [`Stack::set_dns_servers`] are read, the same the socket accepts the answers from.
+pub(crate) struct Snoop<'d, 's, D: Device + ?Sized> {
+    device: &'d mut D,
+    dns: &'s mut DnsState,
+    servers: &'s [IpAddress],
+}
+
+impl<'d, 's, D: Device + ?Sized> Snoop<'d, 's, D> {
+    pub(crate) fn new(device: &'d mut D, dns: &'s mut DnsState, servers: &'s [IpAddress]) -> Self {
+        Self {
+            device,
+            dns,
+            servers,
+        }
+    }
+}
+
+impl<D: Device + ?Sized> Device for Snoop<'_, '_, D> {
+    type RxToken<'t>
+        = SnoopRxToken<'t, D::RxToken<'t>>
+    where
+        Self: 't;
+    type TxToken<'t>
+        = D::TxToken<'t>
+    where
+        Self: 't;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let (rx, tx) = self.device.receive(timestamp)?;
+        let rx = SnoopRxToken {
+            token: rx,
+            dns: self.dns,
+            servers: self.servers,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.device.transmit(timestamp)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+}
+
+pub(crate) struct SnoopRxToken<'t, T: RxToken> {
+    token: T,
+    dns: &'t mut DnsState,
+    servers: &'t [IpAddress],
+}
+
+impl<T: RxToken> RxToken for SnoopRxToken<'_, T> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let dns = self.dns;
+        let servers = self.servers;
+        self.token.consume(|buffer| {
+            dns.snoop(buffer, servers);
+            f(buffer)
+        })
+    }
+
+    fn meta(&self) -> smoltcp::phy::PacketMeta {
+        self.token.meta()
+    }
+}
+
+/// Returns the source address and the payload of the UDP datagram in the Ethernet `frame`
+/// sent from the `port`.
+fn udp_payload(frame: &[u8], port: u16) -> Option<(IpAddress, &[u8])> {
+    let frame = EthernetFrame::new_checked(frame).ok()?;
+    let (source, protocol, payload) = match frame.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let packet = Ipv4Packet::new_checked(frame.payload()).ok()?;
+            (
+                IpAddress::from(packet.src_addr()),
+                packet.next_header(),
+                packet.payload(),
+            )
+        }
+        EthernetProtocol::Ipv6 => {
+            // responses with extension headers are left with the default TTL
+            let packet = Ipv6Packet::new_checked(frame.payload()).ok()?;
+            (
+                IpAddress::from(packet.src_addr()),
+                packet.next_header(),
+                packet.payload(),
+            )
+        }
+        _ => return None,
+    };
+    if protocol != IpProtocol::Udp {
+        return None;
+    }
+
+    let datagram = UdpPacket::new_checked(payload).ok()?;
+    (datagram.src_port() == port).then(|| (source, datagram.payload()))
+}
+
+/// Returns the name asked for by the DNS `response` and the lowest TTL of its A, AAAA
+/// and CNAME answers, which is how long the resolved addresses are valid.
+pub(crate) fn response_ttl(response: &[u8]) -> Option<(CachedName, u32)> {
+    let header = response.get(..HEADER_LEN)?;
+    let flags = u16::from_be_bytes([header[2], header[3]]);
+    let question_count = u16::from_be_bytes([header[4], header[5]]);
+    let answer_count = u16::from_be_bytes([header[6], header[7]]);
+    if flags & FLAG_RESPONSE == 0 || flags & RCODE_MASK != 0 || question_count != 1 {
+        return None;
+    }
+
+    let mut name = CachedName::new();
+    // skip the type and class of the question
+    let mut pos = read_name(response, HEADER_LEN, &mut name)? + 4;
+
+    let mut ttl = None;
+    for _ in 0..answer_count {
+        pos = skip_name(response, pos)?;
+        let record = response.get(pos..pos + 10)?;
+        let record_type = u16::from_be_bytes([record[0], record[1]]);
+        let record_ttl = u32::from_be_bytes([record[4], record[5], record[6], record[7]]);
+        let data_len = usize::from(u16::from_be_bytes([record[8], record[9]]));
+        pos += 10 + data_len;
+
+        if matches!(record_type, TYPE_A | TYPE_AAAA | TYPE_CNAME) {
+            ttl = Some(ttl.map_or(record_ttl, |ttl: u32| ttl.min(record_ttl)));
+        }
+    }
+
+    Some((name, ttl?))
+}
+
+/// Returns the position right after the possibly compressed name starting at `pos`.
+fn skip_name(packet: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *packet.get(pos)?;
+        match len & 0xc0 {
+            0x00 if len == 0 => return Some(pos + 1),
+            0x00 => pos += 1 + usize::from(len),
+            // a pointer ends the name
+            0xc0 => return Some(pos + 2),
+            _ => return None,
+        }
+    }
+}
+
+/// Reads the possibly compressed name starting at `pos` as a dotted string,
+/// the dots and backslashes within the labels are escaped by a backslash.
+///
+/// Returns the position right after the name.
+pub(crate) fn read_name<const N: usize>(
+    packet: &[u8],
+    mut pos: usize,
+    name: &mut heapless::String<N>,
+) -> Option<usize> {
+    let mut end = None;
+    // guards against pointer loops
+    let mut jumps = 0;
+
+    loop {
+        let len = *packet.get(pos)?;
+        match len & 0xc0 {
+            0x00 => {
+                pos += 1;
+                if len == 0 {
+                    return Some(end.unwrap_or(pos));
+                }
+
+                let label = packet.get(pos..pos + usize::from(len))?;
+                if !name.is_empty() {
+                    name.push('.').ok()?;
+                }
+                for c in core::str::from_utf8(label).ok()?.chars() {
+                    if matches!(c, '.' | '\\') {
+                        name.push('\\').ok()?;
+                    }
+                    name.push(c).ok()?;
+                }
+                pos += usize::from(len);
+            }
+            0xc0 => {
+                let pointer = (usize::from(len & 0x3f) << 8) | usize::from(*packet.get(pos + 1)?);
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > 16 {
+                    return None;
+                }
+                pos = pointer;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Cancels the query when the resolving future is dropped before it completes.
+struct QueryGuard<'a> {
+    stack: Stack<'a>,
+    socket: SocketHandle,
+    query: Option<QueryHandle>,
+}
+
+impl Drop for QueryGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(query) = self.query.take() {
+            let socket = self.socket;
+            self.stack.with(|(sockets, _interface)| {
+                sockets.get_mut::<dns::Socket>(socket).cancel_query(query)
+            });
+        }
+    }
+}
+
+impl<'a> Stack<'a> {
+    /// Adds the DNS socket to the stack, so that [`Stack::resolve`] can be used.
+    ///
+    /// `queries` limits how many names can be resolved concurrently.
+    pub fn enable_dns(&mut self, queries: &'a mut [Option<DnsQuery>]) -> Result<(), Error> {
+        let servers = self.dns_servers();
+        let handle = self.add_socket(dns::Socket::new(&servers, queries))?;
+        self.with_dns(|(_sockets, _interface, dns)| dns.handle = Some(handle));
+        Ok(())
+    }
+
+    /// Resolves `name` to its IPv4 addresses using the DNS servers set by
+    /// [`Stack::set_dns_servers`].
+    ///
+    /// Only A records are queried, so the hosts reachable only over IPv6 are not resolved.
+    /// IP address literals, including IPv6 ones, are returned as they are, without any query.
+    ///
+    /// The addresses are cached for the TTL of the answer, at most for [`MAX_CACHE_TTL`].
+    pub async fn resolve(&mut self, name: &str) -> Result<Addresses, Error> {
+        if let Ok(address) = name.parse::<IpAddress>() {
+            let mut addresses = Addresses::new();
+            let _ = addresses.push(address);
+            return Ok(addresses);
+        }
+
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let now = TickTime::now();
+        let (socket, cached) =
+            self.with_dns(|(_sockets, _interface, dns)| (dns.handle, dns.lookup(name, now)));
+        if let Some(addresses) = cached {
+            return Ok(addresses);
+        }
+        let socket = socket.ok_or(Error::InvalidState)?;
+
+        let query = self.with(|(sockets, interface)| {
+            sockets.get_mut::<dns::Socket>(socket).start_query(
+                interface.context(),
+                name,
+                DnsQueryType::A,
+            )
+        })?;
+        let mut guard = QueryGuard {
+            stack: *self,
+            socket,
+            query: Some(query),
+        };
+        self.wake_runner();
+
+        let result = poll_fn(|cx| {
+            self.with(|(sockets, _interface)| {
+                let socket = sockets.get_mut::<dns::Socket>(socket);
+                match socket.get_query_result(query) {
+                    Ok(addresses) => Poll::Ready(Ok(addresses)),
+                    Err(GetQueryResultError::Pending) => {
+                        socket.register_query_waker(query, cx.waker());
+                        Poll::Pending
+                    }
+                    Err(GetQueryResultError::Failed) => Poll::Ready(Err(Error::NameNotResolved)),
+                }
+            })
+        })
+        .await;
+        // the query slot was freed by getting the result
+        guard.query = None;
+
+        let addresses = result?;
+        if addresses.is_empty() {
+            return Err(Error::NameNotResolved);
+        }
+        self.with_dns(|(_sockets, _interface, dns)| dns.insert(name, &addresses, TickTime::now()));
+        Ok(addresses)
+    }
+}
@@ -0,0 +1,134 @@
+use embedded_io_async::ErrorKind;
+use embedded_tls::TlsError;
+use smoltcp::socket::{
+    dns::StartQueryError,
+    tcp::{ConnectError, ListenError, RecvError, SendError},
+    udp::BindError,
+};
+
+/// Error returned by all the liltcp sockets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Error {
+    /// The Ethernet link is down, or it went down while the TCP operation was pending.
+    LinkDown,
+    /// The socket is not connected, either the connection was never established,
+    /// or it was reset by the peer.
+    ConnectionReset,
+    /// The socket is in a state that does not allow the operation, e.g. it is already in use.
+    InvalidState,
+    /// The remote endpoint is unspecified, or there is no local port available.
+    Unaddressable,
+    /// The operation did not complete in time.
+    Timeout,
+    /// All the socket storage slots of the stack, or all the DNS query slots, are taken.
+    NoSocketStorage,
+    /// The peer closed the connection before all the requested data was received.
+    UnexpectedEof,
+    /// The packet does not fit into the buffer.
+    Truncated,
+    /// The hostname is not a valid DNS name.
+    InvalidName,
+    /// The hostname could not be resolved.
+    NameNotResolved,
+    /// A router reported that the destination network is unreachable.
+    NetworkUnreachable,
+    /// A router, or the destination itself, reported that the destination host
+    /// is unreachable.
+    HostUnreachable,
+    /// All the multicast group slots of the stack are taken.
+    GroupTableFull,
+    /// The server asked not to be queried, e.g. the SNTP kiss-o'-death.
+    Refused,
+    /// The TLS session failed, e.g. the server was not authenticated, or it sent an alert
+    /// or a record that could not be decrypted.
+    Tls,
+}
+
+impl From<ConnectError> for Error {
+    fn from(value: ConnectError) -> Self {
+        match value {
+            ConnectError::InvalidState => Self::InvalidState,
+            ConnectError::Unaddressable => Self::Unaddressable,
+        }
+    }
+}
+
+impl From<ListenError> for Error {
+    fn from(value: ListenError) -> Self {
+        match value {
+            ListenError::InvalidState => Self::InvalidState,
+            ListenError::Unaddressable => Self::Unaddressable,
+        }
+    }
+}
+
+impl From<BindError> for Error {
+    fn from(value: BindError) -> Self {
+        match value {
+            BindError::InvalidState => Self::InvalidState,
+            BindError::Unaddressable => Self::Unaddressable,
+        }
+    }
+}
+
+impl From<StartQueryError> for Error {
+    fn from(value: StartQueryError) -> Self {
+        match value {
+            StartQueryError::NoFreeSlot => Self::NoSocketStorage,
+            StartQueryError::InvalidName | StartQueryError::NameTooLong => Self::InvalidName,
+        }
+    }
+}
+
+impl From<SendError> for Error {
+    fn from(value: SendError) -> Self {
+        match value {
+            SendError::InvalidState => Self::ConnectionReset,
+        }
+    }
+}
+
+impl From<RecvError> for Error {
+    fn from(value: RecvError) -> Self {
+        match value {
+            // smoltcp refuses to receive from a socket that is not connected,
+            // or from a socket the peer already closed
+            RecvError::InvalidState | RecvError::Finished => Self::ConnectionReset,
+        }
+    }
+}
+
+impl From<TlsError> for Error {
+    fn from(value: TlsError) -> Self {
+        match value {
+            // the errors of the underlying TcpClient only make it through as the error kind
+            TlsError::Io(ErrorKind::NotConnected) => Self::LinkDown,
+            TlsError::Io(ErrorKind::TimedOut) => Self::Timeout,
+            TlsError::Io(_) | TlsError::ConnectionClosed => Self::ConnectionReset,
+            TlsError::MissingHandshake => Self::InvalidState,
+            _ => Self::Tls,
+        }
+    }
+}
+
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self {
+            Error::LinkDown => embedded_io_async::ErrorKind::NotConnected,
+            Error::ConnectionReset => embedded_io_async::ErrorKind::ConnectionReset,
+            Error::InvalidState => embedded_io_async::ErrorKind::Other,
+            Error::Unaddressable => embedded_io_async::ErrorKind::AddrNotAvailable,
+            Error::Timeout => embedded_io_async::ErrorKind::TimedOut,
+            Error::NoSocketStorage => embedded_io_async::ErrorKind::OutOfMemory,
+            Error::UnexpectedEof => embedded_io_async::ErrorKind::Other,
+            Error::Truncated => embedded_io_async::ErrorKind::InvalidInput,
+            Error::InvalidName => embedded_io_async::ErrorKind::InvalidInput,
+            Error::NameNotResolved => embedded_io_async::ErrorKind::NotFound,
+            Error::NetworkUnreachable => embedded_io_async::ErrorKind::Other,
+            Error::HostUnreachable => embedded_io_async::ErrorKind::Other,
+            Error::GroupTableFull => embedded_io_async::ErrorKind::OutOfMemory,
+            Error::Refused => embedded_io_async::ErrorKind::ConnectionRefused,
+            Error::Tls => embedded_io_async::ErrorKind::InvalidData,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use core::{future::poll_fn, task::Poll};
+
+use lilos::time::{with_timeout, Millis, TickTime};
+use smoltcp::{
+    iface::SocketHandle,
+    phy::ChecksumCapabilities,
+    socket::raw::{self, PacketBuffer, RecvError, SendError},
+    wire::{
+        Icmpv4DstUnreachable, Icmpv4Packet, Icmpv4Repr, IpProtocol, IpVersion, Ipv4Address,
+        Ipv4Packet, Ipv4Repr, IPV4_HEADER_LEN,
+    },
+};
+
+use crate::{stack::Stack, Error};
+
+pub use smoltcp::socket::raw::PacketMetadata;
+
+/// Payload of the echo requests.
+const PING_DATA: &[u8; 16] = b"liltcp ping data";
+
+const ECHO_REQUEST_LEN: usize = IPV4_HEADER_LEN + 8 + PING_DATA.len();
+
+/// ICMP socket for sending echo requests.
+///
+/// It is backed by a raw socket, because smoltcp's ICMP socket doesn't receive
+/// the Destination Unreachable messages sent in response to echo requests.
+pub struct IcmpSocket<'a> {
+    stack: Stack<'a>,
+    handle: SocketHandle,
+    ident: u16,
+    seq_no: u16,
+}
+
+impl<'a> IcmpSocket<'a> {
+    /// Creates a new ICMP socket.
+    ///
+    /// The buffers receive all the incoming ICMP messages, so they should have space
+    /// for a few messages besides the echo replies.
+    pub fn new(
+        mut stack: Stack<'a>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let rx_buffer = PacketBuffer::new(rx_meta, rx_buffer);
+        let tx_buffer = PacketBuffer::new(tx_meta, tx_buffer);
+
+        let socket = raw::Socket::new(IpVersion::Ipv4, IpProtocol::Icmp, rx_buffer, tx_buffer);
+        let handle = stack.add_socket(socket)?;
+
+        Ok(Self {
+            stack,
+            handle,
+            // distinguishes replies to this socket from replies to other sockets and devices
+            ident: u64::from(TickTime::now()) as u16,
+            seq_no: 0,
+        })
+    }
+
+    fn with<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce(&mut raw::Socket) -> U,
+    {
+        self.stack
+            .with(|(sockets, _interface)| f(sockets.get_mut(self.handle)))
+    }
+
+    /// Sends an echo request to `address` and waits for the reply.
+    ///
+    /// Returns the round-trip time. When a router, or the host itself, responds with
+    /// Destination Unreachable, [`Error::NetworkUnreachable`] or [`Error::HostUnreachable`]
+    /// is returned, when there is no response at all, [`Error::Timeout`] is returned.
+    pub async fn ping(&mut self, address: Ipv4Address, timeout: Millis) -> Result<Millis, Error> {
+        if !self.stack.is_link_up() {
+            return Err(Error::LinkDown);
+        }
+
+        let source = self
+            .stack
+            .with(|(_sockets, interface)| interface.ipv4_addr())
+            .ok_or(Error::Unaddressable)?;
+
+        self.seq_no = self.seq_no.wrapping_add(1);
+        let (ident, seq_no) = (self.ident, self.seq_no);
+
+        let mut request = [0u8; ECHO_REQUEST_LEN];
+        emit_echo_request(&mut request, source, address, ident, seq_no);
+
+        poll_fn(|cx| {
+            self.with(|socket| {
+                if request.len() > socket.payload_send_capacity() {
+                    // it would never fit in
+                    return Poll::Ready(Err(Error::Truncated));
+                }
+
+                match socket.send_slice(&request) {
+                    Ok(()) => Poll::Ready(Ok(())),
+                    Err(SendError::BufferFull) => {
+                        socket.register_send_waker(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await?;
+
+        let sent_at = TickTime::now();
+        self.stack.wake_runner();
+
+        let response = poll_fn(|cx| {
+            self.with(|socket| loop {
+                match socket.recv() {
+                    Ok(packet) => {
+                        if let Some(response) = parse_response(packet, address, ident, seq_no) {
+                            return Poll::Ready(response);
+                        }
+                        // some other ICMP message, or a reply to a previous request
+                    }
+                    Err(RecvError::Exhausted) => {
+                        socket.register_recv_waker(cx.waker());
+                        return Poll::Pending;
+                    }
+                    Err(RecvError::Truncated) => {}
+                }
+            })
+        });
+
+        with_timeout(timeout, response)
+            .await
+            .ok_or(Error::Timeout)??;
+
+        Ok(sent_at.elapsed())
+    }
+}
+
+fn emit_echo_request(
+    buffer: &mut [u8; ECHO_REQUEST_LEN],
+    source: Ipv4Address,
+    destination: Ipv4Address,
+    ident: u16,
+    seq_no: u16,
+) {
+    let checksum = ChecksumCapabilities::default();
+
+    let icmp_repr = Icmpv4Repr::EchoRequest {
+        ident,
+        seq_no,
+        data: PING_DATA,
+    };
+    let ip_repr = Ipv4Repr {
+        src_addr: source,
+        dst_addr: destination,
+        next_header: IpProtocol::Icmp,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 64,
+    };
+
+    let (header, payload) = buffer.split_at_mut(IPV4_HEADER_LEN);
+    ip_repr.emit(&mut Ipv4Packet::new_unchecked(header), &checksum);
+    icmp_repr.emit(&mut Icmpv4Packet::new_unchecked(payload), &checksum);
+}
+
+/// Returns `None` if the packet is not a response to the echo request identified
+/// by `ident` and `seq_no`.
+fn parse_response(
+    packet: &[u8],
+    address: Ipv4Address,
+    ident: u16,
+    seq_no: u16,
+) -> Option<Result<(), Error>> {
+    let ip_packet = Ipv4Packet::new_checked(packet).ok()?;
+    let icmp_packet = Icmpv4Packet::new_checked(ip_packet.payload()).ok()?;
+
+    match Icmpv4Repr::parse(&icmp_packet, &ChecksumCapabilities::default()).ok()? {
+        Icmpv4Repr::EchoReply {
+            ident: reply_ident,
+            seq_no: reply_seq_no,
+            ..
+        } if ip_packet.src_addr() == address && reply_ident == ident && reply_seq_no == seq_no => {
+            Some(Ok(()))
+        }
+        Icmpv4Repr::DstUnreachable {
+            reason,
+            header,
+            data,
+        } if header.dst_addr == address && header.next_header == IpProtocol::Icmp => {
+            // the data holds the beginning of the echo request, identifier and
+            // sequence number follow the type, code and checksum
+            if data.len() < 8 {
+                return None;
+            }
+            let original = Icmpv4Packet::new_unchecked(data);
+            if original.echo_ident() != ident || original.echo_seq_no() != seq_no {
+                return None;
+            }
+
+            let error = match reason {
+                Icmpv4DstUnreachable::NetUnreachable
+                | Icmpv4DstUnreachable::DstNetUnknown
+                | Icmpv4DstUnreachable::NetProhibited
+                | Icmpv4DstUnreachable::NetUnreachToS => Error::NetworkUnreachable,
+                _ => Error::HostUnreachable,
+            };
+            Some(Err(error))
+        }
+        _ => None,
+    }
+}
+
+impl Drop for IcmpSocket<'_> {
+    fn drop(&mut self) {
+        self.stack
+            .with(|(sockets, _interface)| sockets.remove(self.handle));
+    }
+}
@@ -1,13 +1,27 @@
 #![no_main]
 #![no_std]
 
+pub mod demo;
+pub mod dhcp;
+pub mod dns;
+pub mod error;
+pub mod icmp;
+pub mod mdns;
+pub mod raw_ethernet;
+pub mod reconnect;
+pub mod slaac;
 pub mod smoltcp_lilos;
+pub mod sntp;
 pub mod stack;
 pub mod tcp;
+pub mod tls;
+pub mod udp;
+
+pub use error::Error;
 
 use core::{
     convert::Infallible,
-    sync::atomic::{self, AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{self, AtomicBool},
 };
 
 use cortex_m_semihosting::debug;
@@ -28,8 +42,12 @@ use stm32h7xx_hal::{
 
 use panic_probe as _;
 
-static COUNT: AtomicUsize = AtomicUsize::new(0);
-defmt::timestamp!("{=usize}", COUNT.fetch_add(1, Ordering::Relaxed));
+#[cfg(not(feature = "utc-log-timestamps"))]
+static COUNT: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+#[cfg(not(feature = "utc-log-timestamps"))]
+defmt::timestamp!("{=usize}", COUNT.fetch_add(1, atomic::Ordering::Relaxed));
+#[cfg(feature = "utc-log-timestamps")]
+defmt::timestamp!("{=u64:iso8601ms}", sntp::log_timestamp());
 
 pub const MAC: smoltcp::wire::EthernetAddress =
     smoltcp::wire::EthernetAddress([0x12, 0x00, 0x00, 0x00, 0x00, 0x00]);
@@ -41,7 +59,8 @@ pub const PREFIX_LEN: u8 = 24;
 
 pub const REMOTE_ENDPOINT: IpEndpoint =
     IpEndpoint::new(Ipv4Address::new(10, 106, 0, 198).into_address(), 8001);
-pub const LOCAL_ENDPOINT: u16 = 55128;
+pub const TLS_REMOTE_ENDPOINT: IpEndpoint =
+    IpEndpoint::new(Ipv4Address::new(10, 106, 0, 198).into_address(), 4433);
 
 pub fn initialize_clock(
     pwr: pac::PWR,
@@ -140,6 +159,17 @@ pub unsafe fn enable_eth_interrupt(nvic: &mut pac::NVIC) {
 }
 // ANCHOR_END: enable_eth_interrupt
 
+/// Lets all the multicast frames through the MAC's frame filter, by default only broadcast
+/// frames and frames addressed to the MAC address are received.
+///
+/// This is needed for IPv6, as neighbor discovery relies on multicast, and for receiving
+/// the traffic of the groups joined by [`stack::Stack::join_multicast_group`].
+pub fn pass_all_multicast() {
+    // SAFETY: the register is only written by the HAL during the initialization
+    let eth_mac = unsafe { &*pac::ETHERNET_MAC::ptr() };
+    eth_mac.macpfr.modify(|_, w| w.pm().set_bit());
+}
+
 // same panicking *behavior* as `panic-probe` but doesn't print a panic message
 // this prevents the panic message being printed *twice* when `defmt::panic` is invoked
 #[defmt::panic_handler]
@@ -176,3 +206,138 @@ pub async fn led_task(mut led: ErasedPin<Output>) -> Infallible {
     }
 }
 // ANCHOR_END: led_task
+
+// defmt-test 0.3.0 has the limitation that this `#[tests]` attribute can only be used
+// once within a crate. the module can be in any file but there can only be at most
+// one `#[tests]` module in this library crate
+#[cfg(test)]
+#[defmt_test::tests]
+mod unit_tests {
+    use defmt::assert_eq;
+
+    use crate::{
+        dns::{read_name, response_ttl},
+        mdns::{instance_eq, Service},
+        sntp::ntp_to_unix_millis,
+        tcp::send_vectored_with,
+    };
+
+    #[test]
+    fn vectored_send_resumes_inside_a_buffer() {
+        let bufs: [&[u8]; 4] = [b"head", b"", b"payload", b"!"];
+
+        // sends the bufs through a tx buffer taking at most `chunk` bytes per send
+        let write_all_vectored = |chunk: usize| {
+            let mut written = heapless::Vec::<u8, 16>::new();
+            while written.len() < 12 {
+                let mut space = chunk;
+                let skip = written.len();
+                let result: Result<usize, ()> = send_vectored_with(&bufs, skip, |buf| {
+                    let n = buf.len().min(space);
+                    space -= n;
+                    written.extend_from_slice(&buf[..n]).unwrap();
+                    Ok(n)
+                });
+                assert_eq!(result, Ok(written.len() - skip));
+            }
+            written
+        };
+
+        // the first send stops in the middle of "payload"
+        assert_eq!(&write_all_vectored(6)[..], b"headpayload!");
+        // every send stops in the middle of a buffer
+        assert_eq!(&write_all_vectored(3)[..], b"headpayload!");
+        assert_eq!(&write_all_vectored(1)[..], b"headpayload!");
+        // everything fits at once
+        assert_eq!(&write_all_vectored(16)[..], b"headpayload!");
+    }
+
+    #[test]
+    fn vectored_send_stops_on_full_buffer() {
+        let bufs: [&[u8]; 2] = [b"head", b"payload"];
+
+        let result: Result<usize, ()> = send_vectored_with(&bufs, 2, |buf| Ok(buf.len().min(1)));
+        assert_eq!(result, Ok(1));
+    }
+
+    #[test]
+    fn dns_response_ttl_is_the_lowest_answer_ttl() {
+        #[rustfmt::skip]
+        let mut response = [
+            // ID, flags (response, recursion available), 1 question, 2 answers
+            0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+            // www.example.com, type A, class IN
+            3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+            0x00, 0x01, 0x00, 0x01,
+            // www.example.com CNAME example.com, TTL 300
+            0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x02, 0xc0, 0x10,
+            // example.com A 93.184.216.34, TTL 60
+            0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 93, 184, 216, 34,
+        ];
+
+        let (name, ttl) = defmt::unwrap!(response_ttl(&response));
+        assert_eq!(name.as_str(), "www.example.com");
+        assert_eq!(ttl, 60);
+
+        // NXDOMAIN
+        response[3] = 0x83;
+        assert_eq!(response_ttl(&response), None);
+    }
+
+    #[test]
+    fn mdns_instance_name_is_a_single_label() {
+        let service = Service {
+            instance: "Board 1.2",
+            service: "_http._tcp",
+            port: 80,
+        };
+
+        #[rustfmt::skip]
+        let single = [
+            9, b'B', b'o', b'a', b'r', b'd', b' ', b'1', b'.', b'2',
+            5, b'_', b'h', b't', b't', b'p', 4, b'_', b't', b'c', b'p', 5, b'l', b'o', b'c', b'a', b'l', 0,
+        ];
+        let mut name = heapless::String::<64>::new();
+        assert_eq!(read_name(&single, 0, &mut name), Some(single.len()));
+        assert_eq!(name.as_str(), "Board 1\\.2._http._tcp.local");
+        assert!(instance_eq(&name, &service));
+
+        #[rustfmt::skip]
+        let split = [
+            7, b'B', b'o', b'a', b'r', b'd', b' ', b'1', 1, b'2',
+            5, b'_', b'h', b't', b't', b'p', 4, b'_', b't', b'c', b'p', 5, b'l', b'o', b'c', b'a', b'l', 0,
+        ];
+        let mut name = heapless::String::<64>::new();
+        assert_eq!(read_name(&split, 0, &mut name), Some(split.len()));
+        assert!(!instance_eq(&name, &service));
+    }
+
+    #[test]
+    fn ntp_timestamps_around_the_era_boundary() {
+        // the Unix epoch
+        assert_eq!(
+            ntp_to_unix_millis(&[0x83, 0xaa, 0x7e, 0x80, 0, 0, 0, 0]),
+            Some(0)
+        );
+        // a half second later
+        assert_eq!(
+            ntp_to_unix_millis(&[0x83, 0xaa, 0x7e, 0x80, 0x80, 0, 0, 0]),
+            Some(500)
+        );
+        // a second before the Unix epoch, in 1969
+        assert_eq!(
+            ntp_to_unix_millis(&[0x83, 0xaa, 0x7e, 0x7f, 0, 0, 0, 0]),
+            None
+        );
+        // the last second of era 0, 2036-02-07 06:28:15
+        assert_eq!(
+            ntp_to_unix_millis(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]),
+            Some(2_085_978_495_000)
+        );
+        // the first second of era 1
+        assert_eq!(
+            ntp_to_unix_millis(&[0, 0, 0, 0, 0, 0, 0, 0]),
+            Some(2_085_978_496_000)
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use core::{convert::Infallible, fmt::Write, num::NonZeroU8};
+
+use lilos::time::{with_timeout, Millis};
+use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, Ipv4Address};
+
+use crate::{
+    dns::read_name,
+    stack::Stack,
+    udp::{PacketMetadata, UdpSocket},
+    Error,
+};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_GROUP: Ipv4Address = Ipv4Address::new(224, 0, 0, 251);
+
+/// TTL of the records containing the hostname, RFC 6762, section 10.
+const HOST_TTL: u32 = 120;
+/// TTL of the other records.
+const OTHER_TTL: u32 = 4500;
+/// Responses to queries not sent from the mDNS port must not be cached for long.
+const LEGACY_UNICAST_TTL: u32 = 10;
+
+/// How often the interface addresses are checked for changes that need to be announced.
+const ANNOUNCE_CHECK_INTERVAL: Millis = Millis(1_000);
+
+/// mDNS packets can be larger, but the queries for a single host rarely are.
+const PACKET_LEN: usize = 512;
+const MAX_NAME_LEN: usize = 255;
+const MAX_QUESTIONS: usize = 8;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+const CACHE_FLUSH: u16 = 0x8000;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const OPCODE_MASK: u16 = 0x7800;
+
+const HEADER_LEN: usize = 12;
+
+const SERVICES_NAME: &str = "_services._dns-sd._udp.local";
+
+type Addresses = heapless::Vec<IpAddress, { smoltcp::config::IFACE_MAX_ADDR_COUNT }>;
+type Name = heapless::String<MAX_NAME_LEN>;
+
+/// A DNS-SD service advertised by the [`MdnsResponder`].
+#[derive(Clone, Copy, Debug)]
+pub struct Service<'s> {
+    /// The human readable instance name, e.g. `"Sensor board 1.2"`, at most 63 bytes.
+    ///
+    /// It is a single label, so it can contain dots.
+    pub instance: &'s str,
+    /// The service type and protocol, e.g. `"_http._tcp"`.
+    pub service: &'s str,
+    pub port: u16,
+}
+
+/// Answers the mDNS queries for `<hostname>.local` and the DNS-SD queries for the `services`.
+///
+/// smoltcp doesn't support joining IPv6 multicast groups, so the responder only listens
+/// on IPv4, the AAAA records are answered there too.
+///
+/// The names are not probed before they are announced and the conflicting responses
+/// of other hosts are ignored, RFC 6762, sections 8.1 and 9, so the hostname
+/// and the instance names have to be unique on the link.
+pub struct MdnsResponder<'a> {
+    socket: UdpSocket<'a>,
+    stack: Stack<'a>,
+    hostname: &'a str,
+    services: &'a [Service<'a>],
+}
+
+impl<'a> MdnsResponder<'a> {
+    pub fn new(
+        mut stack: Stack<'a>,
+        hostname: &'a str,
+        services: &'a [Service<'a>],
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer)?;
+        socket.bind(MDNS_PORT)?;
+        // RFC 6762, section 11, receivers drop the packets that might have come
+        // from outside the link
+        socket.set_hop_limit(NonZeroU8::new(255));
+        stack.join_multicast_group(MDNS_GROUP)?;
+
+        Ok(Self {
+            socket,
+            stack,
+            hostname,
+            services,
+        })
+    }
+
+    pub async fn run(&mut self) -> Infallible {
+        let mut rx = [0u8; PACKET_LEN];
+        let mut tx = [0u8; PACKET_LEN];
+        let mut announced = Addresses::new();
+        let mut announcements = 0;
+
+        loop {
+            // the addresses are announced on change, e.g. when DHCP assigns one,
+            // twice as RFC 6762, section 8.3 requires
+            let addresses = self.addresses();
+            if addresses != announced {
+                announced = addresses;
+                announcements = 2;
+            }
+            if announcements > 0 && self.stack.is_link_up() {
+                announcements -= 1;
+                if let Some(len) = self.announcement(&mut tx) {
+                    let _ = self
+                        .socket
+                        .send_to(&tx[..len], (MDNS_GROUP, MDNS_PORT))
+                        .await;
+                }
+            }
+
+            let Some(Ok((len, remote))) =
+                with_timeout(ANNOUNCE_CHECK_INTERVAL, self.socket.recv_from(&mut rx)).await
+            else {
+                continue;
+            };
+
+            // RFC 6762, section 6.7, queries not sent from the mDNS port are answered
+            // directly, as an ordinary DNS server would
+            let legacy = remote.port != MDNS_PORT;
+            let destination = if legacy {
+                remote
+            } else {
+                IpEndpoint::new(MDNS_GROUP.into_address(), MDNS_PORT)
+            };
+
+            if let Some(len) = self.response(&rx[..len], &mut tx, legacy) {
+                let _ = self.socket.send_to(&tx[..len], destination).await;
+            }
+        }
+    }
+
+    fn addresses(&mut self) -> Addresses {
+        self.stack.with(|(_sockets, interface)| {
+            interface.ip_addrs().iter().map(IpCidr::address).collect()
+        })
+    }
+
+    fn announcement(&mut self, tx: &mut [u8]) -> Option<usize> {
+        let addresses = self.addresses();
+        let mut writer = Writer::new(tx, true);
+        writer.header(0, 0)?;
+
+        let mut answers = 0;
+        answers += self.answer_host(&mut writer, TYPE_ANY, &addresses, HOST_TTL)?;
+        for service in self.services {
+            // RFC 6763, section 8.3, the instance is announced by its PTR record as well
+            answers += answer_service(&mut writer, service, OTHER_TTL)?;
+            answers += self.answer_instance(&mut writer, service, TYPE_ANY, OTHER_TTL)?;
+        }
+
+        writer.set_answer_count(answers);
+        Some(writer.len)
+    }
+
+    fn response(&mut self, query: &[u8], tx: &mut [u8], legacy: bool) -> Option<usize> {
+        if query.len() < HEADER_LEN {
+            return None;
+        }
+        let id = u16::from_be_bytes([query[0], query[1]]);
+        let flags = u16::from_be_bytes([query[2], query[3]]);
+        let question_count = u16::from_be_bytes([query[4], query[5]]);
+        if flags & (FLAG_RESPONSE | OPCODE_MASK) != 0 {
+            // responses of other responders, or not a standard query
+            return None;
+        }
+
+        let (host_ttl, other_ttl) = if legacy {
+            (LEGACY_UNICAST_TTL, LEGACY_UNICAST_TTL)
+        } else {
+            (HOST_TTL, OTHER_TTL)
+        };
+        let addresses = self.addresses();
+
+        let mut questions_end = HEADER_LEN;
+        let mut questions = heapless::Vec::<(Name, u16), MAX_QUESTIONS>::new();
+        // the remaining questions are ignored
+        for _ in 0..usize::from(question_count).min(MAX_QUESTIONS) {
+            let mut name = Name::new();
+            questions_end = read_name(query, questions_end, &mut name)?;
+            let question_type = query.get(questions_end..questions_end + 2)?;
+            let question_type = u16::from_be_bytes([question_type[0], question_type[1]]);
+            // skip the type and class
+            questions_end += 4;
+
+            let _ = questions.push((name, question_type));
+        }
+
+        let mut writer = Writer::new(tx, !legacy);
+        if legacy {
+            // the legacy resolvers need the query ID and the question section repeated
+            writer.bytes(query.get(..questions_end)?)?;
+            writer.header(id, questions.len() as u16)?;
+        } else {
+            writer.header(0, 0)?;
+        }
+
+        let mut answers = 0;
+        for (name, question_type) in &questions {
+            if name_eq(name, &[self.hostname, "local"]) {
+                answers += self.answer_host(&mut writer, *question_type, &addresses, host_ttl)?;
+            }
+
+            if name_eq(name, &[SERVICES_NAME]) && matches!(*question_type, TYPE_PTR | TYPE_ANY) {
+                for (i, service) in self.services.iter().enumerate() {
+                    // each service type is listed only once
+                    if self.services[..i]
+                        .iter()
+                        .any(|other| other.service.eq_ignore_ascii_case(service.service))
+                    {
+                        continue;
+                    }
+                    writer.record(
+                        |writer| writer.name(&[SERVICES_NAME]),
+                        TYPE_PTR,
+                        false,
+                        other_ttl,
+                        |writer| writer.name(&[service.service, "local"]),
+                    )?;
+                    answers += 1;
+                }
+            }
+
+            for service in self.services {
+                if name_eq(name, &[service.service, "local"])
+                    && matches!(*question_type, TYPE_PTR | TYPE_ANY)
+                {
+                    answers += answer_service(&mut writer, service, other_ttl)?;
+                }
+
+                if instance_eq(name, service) {
+                    answers +=
+                        self.answer_instance(&mut writer, service, *question_type, other_ttl)?;
+                }
+            }
+        }
+
+        if answers == 0 {
+            return None;
+        }
+        writer.set_answer_count(answers);
+        Some(writer.len)
+    }
+
+    fn answer_host(
+        &self,
+        writer: &mut Writer<'_>,
+        question_type: u16,
+        addresses: &Addresses,
+        ttl: u32,
+    ) -> Option<u16> {
+        let host = |writer: &mut Writer<'_>| writer.name(&[self.hostname, "local"]);
+        let mut answers = 0;
+        for address in addresses {
+            match address {
+                IpAddress::Ipv4(address) if matches!(question_type, TYPE_A | TYPE_ANY) => {
+                    writer.record(host, TYPE_A, true, ttl, |writer| {
+                        writer.bytes(address.as_bytes())
+                    })?;
+                    answers += 1;
+                }
+                IpAddress::Ipv6(address) if matches!(question_type, TYPE_AAAA | TYPE_ANY) => {
+                    writer.record(host, TYPE_AAAA, true, ttl, |writer| {
+                        writer.bytes(address.as_bytes())
+                    })?;
+                    answers += 1;
+                }
+                _ => {}
+            }
+        }
+        Some(answers)
+    }
+
+    fn answer_instance(
+        &self,
+        writer: &mut Writer<'_>,
+        service: &Service<'_>,
+        question_type: u16,
+        ttl: u32,
+    ) -> Option<u16> {
+        let name = |writer: &mut Writer<'_>| writer.instance_name(service);
+        let mut answers = 0;
+
+        if matches!(question_type, TYPE_SRV | TYPE_ANY) {
+            writer.record(name, TYPE_SRV, true, ttl, |writer| {
+                // priority and weight
+                writer.u16(0)?;
+                writer.u16(0)?;
+                writer.u16(service.port)?;
+                writer.name(&[self.hostname, "local"])
+            })?;
+            answers += 1;
+        }
+
+        if matches!(question_type, TYPE_TXT | TYPE_ANY) {
+            // RFC 6763, section 6.1, an empty TXT record has a single empty string
+            writer.record(name, TYPE_TXT, true, ttl, |writer| writer.bytes(&[0]))?;
+            answers += 1;
+        }
+
+        Some(answers)
+    }
+}
+
+impl Drop for MdnsResponder<'_> {
+    fn drop(&mut self) {
+        self.stack.leave_multicast_group(MDNS_GROUP);
+    }
+}
+
+/// Writes the PTR record pointing from the service type to the service instance.
+///
+/// The record is shared with the other instances of the same type, so it doesn't flush
+/// the caches.
+fn answer_service(writer: &mut Writer<'_>, service: &Service<'_>, ttl: u32) -> Option<u16> {
+    writer.record(
+        |writer| writer.name(&[service.service, "local"]),
+        TYPE_PTR,
+        false,
+        ttl,
+        |writer| writer.instance_name(service),
+    )?;
+    Some(1)
+}
+
+/// Compares the `name` with the `parts` joined with dots, ignoring the case.
+fn name_eq(name: &str, parts: &[&str]) -> bool {
+    let mut expected = Name::new();
+    for part in parts {
+        if !expected.is_empty() && expected.push('.').is_err() {
+            return false;
+        }
+        if expected.write_str(part).is_err() {
+            return false;
+        }
+    }
+    name.eq_ignore_ascii_case(&expected)
+}
+
+/// Compares the `name` read by [`read_name`] with the name of the service instance.
+pub(crate) fn instance_eq(name: &str, service: &Service<'_>) -> bool {
+    let mut instance = Name::new();
+    for c in service.instance.chars() {
+        if matches!(c, '.' | '\\') && instance.push('\\').is_err() {
+            return false;
+        }
+        if instance.push(c).is_err() {
+            return false;
+        }
+    }
+    name_eq(name, &[&instance, service.service, "local"])
+}
+
+/// Writes a DNS response, all the methods return `None` when the buffer is full.
+struct Writer<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+    cache_flush: bool,
+}
+
+impl<'b> Writer<'b> {
+    /// The cache-flush bit must not be set in the responses to legacy resolvers.
+    fn new(buffer: &'b mut [u8], cache_flush: bool) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            cache_flush,
+        }
+    }
+
+    /// Writes the header to the beginning of the buffer, the answer count is set later.
+    fn header(&mut self, id: u16, question_count: u16) -> Option<()> {
+        let header = self.buffer.get_mut(..HEADER_LEN)?;
+        header.fill(0);
+        header[0..2].copy_from_slice(&id.to_be_bytes());
+        header[2..4].copy_from_slice(&(FLAG_RESPONSE | FLAG_AUTHORITATIVE).to_be_bytes());
+        header[4..6].copy_from_slice(&question_count.to_be_bytes());
+        self.len = self.len.max(HEADER_LEN);
+        Some(())
+    }
+
+    fn set_answer_count(&mut self, count: u16) {
+        self.buffer[6..8].copy_from_slice(&count.to_be_bytes());
+    }
+
+    fn bytes(&mut self, data: &[u8]) -> Option<()> {
+        let end = self.len + data.len();
+        self.buffer.get_mut(self.len..end)?.copy_from_slice(data);
+        self.len = end;
+        Some(())
+    }
+
+    fn u16(&mut self, value: u16) -> Option<()> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    fn u32(&mut self, value: u32) -> Option<()> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    /// Writes the `parts` joined with dots as an uncompressed name.
+    fn name(&mut self, parts: &[&str]) -> Option<()> {
+        for part in parts {
+            for label in part.split('.').filter(|label| !label.is_empty()) {
+                self.label(label)?;
+            }
+        }
+        self.bytes(&[0])
+    }
+
+    /// Writes the name of the service instance, the instance is a single label
+    /// even if it contains dots, RFC 6763, section 4.3.
+    fn instance_name(&mut self, service: &Service<'_>) -> Option<()> {
+        self.label(service.instance)?;
+        self.name(&[service.service, "local"])
+    }
+
+    fn label(&mut self, label: &str) -> Option<()> {
+        self.bytes(&[u8::try_from(label.len()).ok().filter(|len| *len < 64)?])?;
+        self.bytes(label.as_bytes())
+    }
+
+    fn record(
+        &mut self,
+        name: impl FnOnce(&mut Self) -> Option<()>,
+        record_type: u16,
+        unique: bool,
+        ttl: u32,
+        data: impl FnOnce(&mut Self) -> Option<()>,
+    ) -> Option<()> {
+        name(self)?;
+        self.u16(record_type)?;
+        // RFC 6762, section 10.2, records owned only by this host flush the caches
+        self.u16(if unique && self.cache_flush {
+            CLASS_IN | CACHE_FLUSH
+        } else {
+            CLASS_IN
+        })?;
+        self.u32(ttl)?;
+
+        let len_pos = self.len;
+        self.u16(0)?;
+        data(self)?;
+        let data_len = u16::try_from(self.len - len_pos - 2).ok()?;
+        self.buffer[len_pos..len_pos + 2].copy_from_slice(&data_len.to_be_bytes());
+        Some(())
+    }
+}
@@ -0,0 +1,273 @@
+use core::{future::poll_fn, task::Poll, task::Waker};
+
+use smoltcp::{
+    iface::Interface,
+    phy::{Device, DeviceCapabilities, RxToken, TxToken},
+    storage::PacketBuffer,
+    time::Instant,
+    wire::{
+        EthernetAddress, EthernetFrame, EthernetProtocol, HardwareAddress, ETHERNET_HEADER_LEN,
+    },
+};
+
+use crate::{stack::Stack, Error};
+
+pub type PacketMetadata = smoltcp::storage::PacketMetadata<EthernetAddress>;
+
+/// The maximum number of raw Ethernet sockets.
+pub const MAX_ETHERNET_SOCKETS: usize = 2;
+
+/// The maximum payload of a frame, without jumbo frames.
+const MAX_PAYLOAD_LEN: usize = 1500;
+
+pub(crate) struct EthernetSocketState<'a> {
+    ethertype: u16,
+    rx: PacketBuffer<'a, EthernetAddress>,
+    tx: PacketBuffer<'a, EthernetAddress>,
+    rx_waker: Option<Waker>,
+    tx_waker: Option<Waker>,
+}
+
+pub(crate) type EthernetSockets<'a> = [Option<EthernetSocketState<'a>>; MAX_ETHERNET_SOCKETS];
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+/// Raw Ethernet socket sending and receiving the frames of a single EtherType.
+///
+/// smoltcp has no sockets below the IP layer, so the frames are picked up by [`Stack::poll`]
+/// on their way to the interface, which ignores the EtherTypes it doesn't know.
+pub struct EthernetSocket<'a> {
+    stack: Stack<'a>,
+    slot: usize,
+}
+
+impl<'a> EthernetSocket<'a> {
+    /// Creates a socket receiving the frames of the `ethertype`.
+    ///
+    /// The buffers hold the payloads of the frames, without the Ethernet header.
+    pub fn new(
+        mut stack: Stack<'a>,
+        ethertype: u16,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let state = EthernetSocketState {
+            ethertype,
+            rx: PacketBuffer::new(rx_meta, rx_buffer),
+            tx: PacketBuffer::new(tx_meta, tx_buffer),
+            rx_waker: None,
+            tx_waker: None,
+        };
+
+        let slot = stack.with_ethernet(|(sockets, _interface)| {
+            if sockets
+                .iter()
+                .flatten()
+                .any(|socket| socket.ethertype == ethertype)
+            {
+                return Err(Error::InvalidState);
+            }
+
+            let slot = sockets
+                .iter()
+                .position(Option::is_none)
+                .ok_or(Error::NoSocketStorage)?;
+            sockets[slot] = Some(state);
+            Ok(slot)
+        })?;
+
+        Ok(Self { stack, slot })
+    }
+
+    fn with<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce(&mut EthernetSocketState<'a>) -> U,
+    {
+        let slot = self.slot;
+        self.stack.with_ethernet(|(sockets, _interface)| {
+            // the slot is only freed when the socket is dropped
+            f(sockets[slot].as_mut().unwrap())
+        })
+    }
+
+    /// Waits until there is space for the frame in the tx buffer and queues it.
+    ///
+    /// The source address and the EtherType are filled in.
+    pub async fn send(
+        &mut self,
+        destination: EthernetAddress,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        poll_fn(|cx| {
+            self.with(|socket| {
+                if payload.len() > MAX_PAYLOAD_LEN || payload.len() > socket.tx.payload_capacity() {
+                    // it would never fit in
+                    return Poll::Ready(Err(Error::Truncated));
+                }
+
+                match socket.tx.enqueue(payload.len(), destination) {
+                    Ok(buffer) => {
+                        buffer.copy_from_slice(payload);
+                        Poll::Ready(Ok(()))
+                    }
+                    Err(_) => {
+                        socket.tx_waker = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await?;
+
+        self.stack.wake_runner();
+        Ok(())
+    }
+
+    /// Waits for a frame and copies its payload to `buf`.
+    ///
+    /// Returns the length of the payload and the address the frame was sent from.
+    /// If the payload doesn't fit into `buf`, it is dropped and [`Error::Truncated`] is returned.
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<(usize, EthernetAddress), Error> {
+        poll_fn(|cx| {
+            self.with(|socket| match socket.rx.dequeue() {
+                Ok((source, payload)) => {
+                    if payload.len() > buf.len() {
+                        return Poll::Ready(Err(Error::Truncated));
+                    }
+                    buf[..payload.len()].copy_from_slice(payload);
+                    Poll::Ready(Ok((payload.len(), source)))
+                }
+                Err(_) => {
+                    socket.rx_waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+}
+
+impl Drop for EthernetSocket<'_> {
+    fn drop(&mut self) {
+        let slot = self.slot;
+        self.stack
+            .with_ethernet(|(sockets, _interface)| sockets[slot] = None);
+    }
+}
+
+/// Sends a single frame queued by each of the sockets, as long as the device has free
+/// tx buffers, so that the sockets can't take all of them from the interface.
+///
+/// Returns whether any frames are left in the queues.
+pub(crate) fn transmit<D: Device + ?Sized>(
+    sockets: &mut EthernetSockets<'_>,
+    interface: &Interface,
+    device: &mut D,
+    now: Instant,
+) -> bool {
+    let HardwareAddress::Ethernet(source) = interface.hardware_addr();
+
+    for socket in sockets.iter_mut().flatten() {
+        if let Ok((&destination, payload)) = socket.tx.peek() {
+            let Some(token) = device.transmit(now) else {
+                return true;
+            };
+
+            token.consume(ETHERNET_HEADER_LEN + payload.len(), |buffer| {
+                let mut frame = EthernetFrame::new_unchecked(buffer);
+                frame.set_dst_addr(destination);
+                frame.set_src_addr(source);
+                frame.set_ethertype(EthernetProtocol::from(socket.ethertype));
+                frame.payload_mut().copy_from_slice(payload);
+            });
+
+            let _ = socket.tx.dequeue();
+            wake(&mut socket.tx_waker);
+        }
+    }
+
+    sockets.iter().flatten().any(|socket| !socket.tx.is_empty())
+}
+
+/// Device passing the received frames to the interface, while handing out copies
+/// of the frames of the sockets' EtherTypes to the sockets.
+pub(crate) struct Tap<'d, 's, 'e, D: Device + ?Sized> {
+    device: &'d mut D,
+    sockets: &'s mut EthernetSockets<'e>,
+}
+
+impl<'d, 's, 'e, D: Device + ?Sized> Tap<'d, 's, 'e, D> {
+    pub(crate) fn new(device: &'d mut D, sockets: &'s mut EthernetSockets<'e>) -> Self {
+        Self { device, sockets }
+    }
+}
+
+impl<'e, D: Device + ?Sized> Device for Tap<'_, '_, 'e, D> {
+    type RxToken<'t>
+        = TapRxToken<'t, 'e, D::RxToken<'t>>
+    where
+        Self: 't;
+    type TxToken<'t>
+        = D::TxToken<'t>
+    where
+        Self: 't;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let (rx, tx) = self.device.receive(timestamp)?;
+        let rx = TapRxToken {
+            token: rx,
+            sockets: self.sockets,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.device.transmit(timestamp)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+}
+
+pub(crate) struct TapRxToken<'t, 'e, T: RxToken> {
+    token: T,
+    sockets: &'t mut EthernetSockets<'e>,
+}
+
+impl<T: RxToken> RxToken for TapRxToken<'_, '_, T> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let sockets = self.sockets;
+        self.token.consume(|buffer| {
+            if let Ok(frame) = EthernetFrame::new_checked(&*buffer) {
+                let ethertype = u16::from(frame.ethertype());
+                for socket in sockets.iter_mut().flatten() {
+                    if socket.ethertype != ethertype {
+                        continue;
+                    }
+                    // the frame is dropped if the socket has no space for it
+                    if let Ok(payload) = socket.rx.enqueue(frame.payload().len(), frame.src_addr())
+                    {
+                        payload.copy_from_slice(frame.payload());
+                        wake(&mut socket.rx_waker);
+                    }
+                }
+            }
+
+            f(buffer)
+        })
+    }
+
+    fn meta(&self) -> smoltcp::phy::PacketMeta {
+        self.token.meta()
+    }
+}
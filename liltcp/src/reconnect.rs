@@ -0,0 +1,216 @@
+use core::{cell::Cell, convert::Infallible};
+
+use embassy_futures::select::select;
+use lilos::{
+    exec::Notify,
+    time::{sleep_for, Millis, TickTime},
+};
+use smoltcp::{socket::tcp, time::Duration, wire::IpEndpoint};
+
+use crate::{
+    tcp::{TcpClient, TcpIo, TcpOptions},
+    Error,
+};
+
+/// How often the connection is checked when there is no socket activity.
+const CHECK_INTERVAL: Millis = Millis(500);
+
+/// Keep-alive interval set by [`ReconnectingClient::new`].
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+/// Socket timeout set by [`ReconnectingClient::new`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Exponential backoff configuration of the [`ReconnectingClient`].
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    /// The delay after the first failed attempt.
+    pub initial: Millis,
+    /// The upper bound of the delay, the delay doubles after each failed attempt.
+    pub max: Millis,
+    /// Time given to a single connection attempt.
+    pub connect_timeout: Millis,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Millis(250),
+            max: Millis(30_000),
+            connect_timeout: Millis(5_000),
+        }
+    }
+}
+
+/// A TCP client that keeps its connection up.
+///
+/// The connection is maintained by [`ReconnectingClient::run`], which has to be polled
+/// by a task for the whole lifetime of the client. Other tasks then use
+/// [`ReconnectingClient::send`] and [`ReconnectingClient::recv`], which wait until
+/// the client is connected.
+pub struct ReconnectingClient<'a> {
+    client: TcpClient<'a>,
+    remote_endpoint: IpEndpoint,
+    backoff: Backoff,
+    connected: Cell<bool>,
+    state_changed: Notify,
+    jitter_seed: Cell<u32>,
+}
+
+impl<'a> ReconnectingClient<'a> {
+    /// Wraps the `client`, setting its keep-alive and timeout to [`DEFAULT_KEEP_ALIVE`]
+    /// and [`DEFAULT_TIMEOUT`], the other options of the socket are kept.
+    ///
+    /// Without them, a peer that disappears while the connection is idle is never noticed.
+    /// Use [`ReconnectingClient::with_options`] to choose them, or to disable them.
+    pub fn new(
+        client: TcpClient<'a>,
+        remote_endpoint: impl Into<IpEndpoint>,
+        backoff: Backoff,
+    ) -> Self {
+        client.io().with(|socket, _context| {
+            socket.set_keep_alive(Some(DEFAULT_KEEP_ALIVE));
+            socket.set_timeout(Some(DEFAULT_TIMEOUT));
+        });
+
+        Self::wrap(client, remote_endpoint, backoff)
+    }
+
+    /// Same as [`ReconnectingClient::new`], but applies the `options` to the socket as they
+    /// are, `None` keep-alive or timeout disables it.
+    pub fn with_options(
+        mut client: TcpClient<'a>,
+        remote_endpoint: impl Into<IpEndpoint>,
+        backoff: Backoff,
+        options: TcpOptions,
+    ) -> Self {
+        client.set_options(options);
+
+        Self::wrap(client, remote_endpoint, backoff)
+    }
+
+    fn wrap(
+        client: TcpClient<'a>,
+        remote_endpoint: impl Into<IpEndpoint>,
+        backoff: Backoff,
+    ) -> Self {
+        Self {
+            client,
+            remote_endpoint: remote_endpoint.into(),
+            backoff,
+            connected: Cell::new(false),
+            state_changed: Notify::new(),
+            jitter_seed: Cell::new(0),
+        }
+    }
+
+    fn io(&self) -> TcpIo<'a> {
+        self.client.io()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.get()
+    }
+
+    /// Waits until the connection is established.
+    pub async fn wait_connected(&self) {
+        self.state_changed.until(|| self.connected.get()).await
+    }
+
+    /// Keeps (re)establishing the connection.
+    pub async fn run(&self) -> Infallible {
+        let mut io = self.io();
+
+        loop {
+            let mut delay = self.backoff.initial;
+            while let Err(e) = io
+                .connect_with_timeout(self.remote_endpoint, self.backoff.connect_timeout)
+                .await
+            {
+                defmt::warn!("Connecting failed: {}, retrying in {} ms", e, delay.0);
+                // reset the socket in case it is stuck in the middle of a handshake
+                io.abort();
+                sleep_for(self.jitter(delay)).await;
+                delay = Millis((delay.0 * 2).min(self.backoff.max.0));
+            }
+
+            defmt::info!("Connected.");
+            self.set_connected(true);
+
+            // connection loss is either reported by the application tasks,
+            // or found out by the periodic check
+            while self.is_alive(&mut io) {
+                select(self.state_changed.until_next(), sleep_for(CHECK_INTERVAL)).await;
+            }
+
+            defmt::warn!("Connection lost.");
+            self.set_connected(false);
+            // the RST is sent by the stack before connecting again, see `TcpIo::connect`
+            io.abort();
+        }
+    }
+
+    fn is_alive(&self, io: &mut TcpIo<'_>) -> bool {
+        self.connected.get()
+            && io.stack().is_link_up()
+            && io.with(|socket, _context| socket.state() == tcp::State::Established)
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.connected.set(connected);
+        self.state_changed.notify();
+    }
+
+    /// Randomizes the delay to be between a half and the full `delay`, so that many devices
+    /// restarted at once don't reconnect in lockstep.
+    fn jitter(&self, delay: Millis) -> Millis {
+        // xorshift32, seeded by the time of the first failure
+        let mut x = match self.jitter_seed.get() {
+            0 => u64::from(TickTime::now()) as u32 | 1,
+            seed => seed,
+        };
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.jitter_seed.set(x);
+
+        let half = delay.0 / 2;
+        Millis(half + u64::from(x) % (half + 1))
+    }
+
+    /// Waits until connected and sends the data, see [`TcpClient::send`].
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, Error> {
+        self.wait_connected().await;
+        let result = self.io().send(buf).await;
+        self.check(result)
+    }
+
+    /// Waits until connected and sends the whole `buf`, see [`TcpClient::write_all`].
+    pub async fn write_all(&self, buf: &[u8]) -> Result<(), Error> {
+        self.wait_connected().await;
+        let result = self.io().write_all(buf).await;
+        self.check(result)
+    }
+
+    /// Waits until connected and receives data, see [`TcpClient::recv`].
+    ///
+    /// When the peer closes the connection, `Ok(0)` is returned and the connection
+    /// is re-established.
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.wait_connected().await;
+        let result = self.io().recv(buf).await;
+        if matches!(result, Ok(0)) && !buf.is_empty() {
+            // EOF, the peer is gone
+            self.set_connected(false);
+        }
+        self.check(result)
+    }
+
+    fn check<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        if result.is_err() {
+            // let the `run` task know
+            self.set_connected(false);
+        }
+        result
+    }
+}
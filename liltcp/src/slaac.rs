@@ -0,0 +1,444 @@
+use core::{
+    convert::Infallible,
+    future::{pending, poll_fn},
+    task::Poll,
+};
+
+use lilos::time::{with_timeout, Millis, TickTime};
+use smoltcp::{
+    iface::{Interface, SocketHandle},
+    phy::ChecksumCapabilities,
+    socket::raw::{self, PacketBuffer, RecvError},
+    time::Duration,
+    wire::{
+        EthernetAddress, HardwareAddress, Icmpv6Packet, Icmpv6Repr, IpCidr, IpProtocol, IpVersion,
+        Ipv6Address, Ipv6Cidr, Ipv6Packet, Ipv6Repr, NdiscPrefixInfoFlags, NdiscRepr,
+        IPV6_HEADER_LEN,
+    },
+};
+
+use crate::{stack::Stack, Error};
+
+pub use smoltcp::socket::raw::PacketMetadata;
+
+/// How long to wait for a router advertisement after sending a solicitation, RFC 4861.
+const RTR_SOLICITATION_INTERVAL: Millis = Millis(4_000);
+/// How many router solicitations are sent before waiting for unsolicited advertisements.
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+
+/// How long to wait for a neighbor advertisement after sending a solicitation, RFC 4861.
+const RETRANS_TIMER: Millis = Millis(1_000);
+/// How many neighbor solicitations are sent by the duplicate address detection, RFC 4862.
+const DUP_ADDR_DETECT_TRANSMITS: u8 = 1;
+
+/// The advertised valid lifetime can shorten the remaining one only down to this,
+/// RFC 4862, section 5.5.3 e).
+const MIN_VALID_LIFETIME: Millis = Millis(2 * 60 * 60 * 1000);
+
+/// Neighbor Solicitation without options, longer than the Router Solicitation
+/// with the Source Link-Layer Address option.
+const MAX_PACKET_LEN: usize = IPV6_HEADER_LEN + 24;
+
+/// Returns the modified EUI-64 interface identifier derived from `mac`, RFC 4291.
+fn interface_identifier(mac: EthernetAddress) -> [u8; 8] {
+    let mac = mac.as_bytes();
+    [
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xff,
+        0xfe,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]
+}
+
+/// Returns the `fe80::/64` link-local address derived from `mac`.
+pub fn link_local_address(mac: EthernetAddress) -> Ipv6Cidr {
+    let mut address = [0u8; 16];
+    address[..2].copy_from_slice(&[0xfe, 0x80]);
+    address[8..].copy_from_slice(&interface_identifier(mac));
+
+    Ipv6Cidr::new(Ipv6Address(address), 64)
+}
+
+struct Lease<T> {
+    value: T,
+    expires_at: Option<TickTime>,
+}
+
+impl<T> Lease<T> {
+    fn new(value: T, lifetime: Duration) -> Self {
+        Self {
+            value,
+            expires_at: expires_at(lifetime, TickTime::now()),
+        }
+    }
+
+    /// Sets the advertised valid `lifetime`, unless it would cut the remaining lifetime
+    /// below [`MIN_VALID_LIFETIME`], so a forged advertisement can't remove the address,
+    /// RFC 4862, section 5.5.3 e).
+    fn update_valid_lifetime(&mut self, lifetime: Duration, now: TickTime) {
+        let received = expires_at(lifetime, now);
+        let min = Some(now + MIN_VALID_LIFETIME);
+
+        if outlasts(received, min) || outlasts(received, self.expires_at) {
+            self.expires_at = received;
+        } else if outlasts(self.expires_at, min) {
+            self.expires_at = min;
+        }
+    }
+
+    fn is_expired(&self, now: TickTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// Returns when the `lifetime` starting `now` ends, `None` is infinity.
+fn expires_at(lifetime: Duration, now: TickTime) -> Option<TickTime> {
+    // all ones is infinity
+    (lifetime.secs() != u64::from(u32::MAX)).then(|| now + Millis(lifetime.millis()))
+}
+
+/// Whether the expiration `a` is later than `b`, `None` being infinity.
+fn outlasts(a: Option<TickTime>, b: Option<TickTime>) -> bool {
+    match (a, b) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(a), Some(b)) => a > b,
+    }
+}
+
+/// IPv6 stateless address autoconfiguration, RFC 4862.
+///
+/// [`Slaac::run`] assigns the link-local address, and then the global address and
+/// the default route from the router advertisements. Only a single global address
+/// is configured, the address of another advertised prefix is formed once the current
+/// one expires.
+///
+/// Each address goes through the duplicate address detection first, a duplicate
+/// link-local address disables the autoconfiguration, RFC 4862, section 5.4.5.
+/// The detection relies on the owner of the address answering the solicitation,
+/// a node detecting the same address at the same time is not noticed, as smoltcp
+/// drops the solicitations for the addresses not yet assigned.
+pub struct Slaac<'a> {
+    stack: Stack<'a>,
+    handle: SocketHandle,
+    mac: EthernetAddress,
+    address: Option<Lease<Ipv6Cidr>>,
+    router: Option<Lease<Ipv6Address>>,
+    solicitations: u8,
+}
+
+impl<'a> Slaac<'a> {
+    /// The buffers receive all the incoming ICMPv6 messages, including neighbor discovery,
+    /// so they should have space for a few messages besides the router advertisements.
+    pub fn new(
+        mut stack: Stack<'a>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let rx_buffer = PacketBuffer::new(rx_meta, rx_buffer);
+        let tx_buffer = PacketBuffer::new(tx_meta, tx_buffer);
+
+        let socket = raw::Socket::new(IpVersion::Ipv6, IpProtocol::Icmpv6, rx_buffer, tx_buffer);
+        let handle = stack.add_socket(socket)?;
+
+        let HardwareAddress::Ethernet(mac) =
+            stack.with(|(_sockets, interface)| interface.hardware_addr());
+
+        Ok(Self {
+            stack,
+            handle,
+            mac,
+            address: None,
+            router: None,
+            solicitations: 0,
+        })
+    }
+
+    pub async fn run(&mut self) -> Infallible {
+        let link_local = link_local_address(self.mac);
+        if self.is_duplicate(link_local.address()).await {
+            defmt::error!("Duplicate link-local address {}, IPv6 disabled", link_local);
+            // the interface has to be configured manually
+            return pending().await;
+        }
+        self.stack
+            .with(|(_sockets, interface)| add_address(interface, link_local));
+
+        loop {
+            if self.router.is_none()
+                && self.solicitations < MAX_RTR_SOLICITATIONS
+                && self.stack.is_link_up()
+            {
+                self.solicit();
+                self.solicitations += 1;
+            }
+
+            if let Some(Message::RouterAdvert(advert)) =
+                with_timeout(RTR_SOLICITATION_INTERVAL, self.next_message()).await
+            {
+                self.apply(advert).await;
+            }
+
+            self.expire();
+        }
+    }
+
+    fn solicit(&mut self) {
+        let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+            lladdr: Some(self.mac.into()),
+        });
+        self.send(
+            link_local_address(self.mac).address(),
+            Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+            icmp_repr,
+        );
+    }
+
+    /// Performs the duplicate address detection of the tentative `address`, RFC 4862,
+    /// section 5.4.
+    ///
+    /// The messages other than the neighbor advertisements received in the meantime
+    /// are dropped.
+    async fn is_duplicate(&mut self, address: Ipv6Address) -> bool {
+        // the solicitations sent while the link is down would be lost
+        poll_fn(|cx| {
+            if self.stack.is_link_up() {
+                Poll::Ready(())
+            } else {
+                self.stack.subscribe_link_changes(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await;
+
+        for _ in 0..DUP_ADDR_DETECT_TRANSMITS {
+            let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit {
+                target_addr: address,
+                lladdr: None,
+            });
+            self.send(
+                Ipv6Address::UNSPECIFIED,
+                address.solicited_node(),
+                icmp_repr,
+            );
+
+            let answered = with_timeout(RETRANS_TIMER, async {
+                loop {
+                    if let Message::NeighborAdvert(target) = self.next_message().await {
+                        if target == address {
+                            return;
+                        }
+                    }
+                }
+            })
+            .await;
+            if answered.is_some() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn send(&mut self, source: Ipv6Address, destination: Ipv6Address, icmp_repr: Icmpv6Repr) {
+        let checksum = ChecksumCapabilities::default();
+        let ip_repr = Ipv6Repr {
+            src_addr: source,
+            dst_addr: destination,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp_repr.buffer_len(),
+            hop_limit: 255,
+        };
+
+        let mut packet = [0u8; MAX_PACKET_LEN];
+        let packet = &mut packet[..ip_repr.buffer_len() + icmp_repr.buffer_len()];
+        let (header, payload) = packet.split_at_mut(IPV6_HEADER_LEN);
+        ip_repr.emit(&mut Ipv6Packet::new_unchecked(header));
+        icmp_repr.emit(
+            &source.into(),
+            &destination.into(),
+            &mut Icmpv6Packet::new_unchecked(payload),
+            &checksum,
+        );
+
+        let handle = self.handle;
+        let sent = self.stack.with(|(sockets, _interface)| {
+            sockets
+                .get_mut::<raw::Socket>(handle)
+                .send_slice(packet)
+                .is_ok()
+        });
+        if sent {
+            self.stack.wake_runner();
+        }
+    }
+
+    async fn next_message(&mut self) -> Message {
+        let handle = self.handle;
+        poll_fn(|cx| {
+            self.stack.with(|(sockets, _interface)| {
+                let socket = sockets.get_mut::<raw::Socket>(handle);
+                loop {
+                    match socket.recv() {
+                        Ok(packet) => {
+                            if let Some(message) = parse_message(packet) {
+                                return Poll::Ready(message);
+                            }
+                        }
+                        Err(RecvError::Exhausted) => {
+                            socket.register_recv_waker(cx.waker());
+                            return Poll::Pending;
+                        }
+                        Err(RecvError::Truncated) => {}
+                    }
+                }
+            })
+        })
+        .await
+    }
+
+    async fn apply(&mut self, advert: RouterAdvert) {
+        let router = &mut self.router;
+        self.stack.with(|(_sockets, interface)| {
+            if advert.router_lifetime == Duration::ZERO {
+                // the router is going away
+                if router.take().is_some() {
+                    interface.routes_mut().remove_default_ipv6_route();
+                }
+            } else {
+                // the routing table has a space for the default route
+                let _ = interface.routes_mut().add_default_ipv6_route(advert.router);
+                *router = Some(Lease::new(advert.router, advert.router_lifetime));
+            }
+        });
+
+        if let Some(prefix) = advert.prefix {
+            self.apply_prefix(prefix).await;
+        }
+    }
+
+    async fn apply_prefix(&mut self, prefix: PrefixInfo) {
+        let mut bytes = prefix.prefix.0;
+        bytes[8..].copy_from_slice(&interface_identifier(self.mac));
+        let cidr = Ipv6Cidr::new(Ipv6Address(bytes), 64);
+
+        match &mut self.address {
+            Some(lease) if lease.value == cidr => {
+                lease.update_valid_lifetime(prefix.valid_lifetime, TickTime::now());
+            }
+            // the current address is kept until it expires
+            Some(_) => {}
+            None if prefix.valid_lifetime == Duration::ZERO => {}
+            None => {
+                if self.is_duplicate(cidr.address()).await {
+                    defmt::warn!("Duplicate SLAAC address {}", cidr);
+                    return;
+                }
+
+                defmt::info!("SLAAC address: {}", cidr);
+                self.stack
+                    .with(|(_sockets, interface)| add_address(interface, cidr));
+                self.address = Some(Lease::new(cidr, prefix.valid_lifetime));
+            }
+        }
+    }
+
+    fn expire(&mut self) {
+        let now = TickTime::now();
+        let (address, router) = (&mut self.address, &mut self.router);
+
+        self.stack.with(|(_sockets, interface)| {
+            if let Some(lease) = address.take_if(|lease| lease.is_expired(now)) {
+                defmt::warn!("SLAAC address {} expired", lease.value);
+                remove_address(interface, lease.value);
+            }
+            if router.take_if(|lease| lease.is_expired(now)).is_some() {
+                interface.routes_mut().remove_default_ipv6_route();
+            }
+        });
+    }
+}
+
+struct PrefixInfo {
+    prefix: Ipv6Address,
+    valid_lifetime: Duration,
+}
+
+struct RouterAdvert {
+    router: Ipv6Address,
+    router_lifetime: Duration,
+    prefix: Option<PrefixInfo>,
+}
+
+enum Message {
+    RouterAdvert(RouterAdvert),
+    /// The target address of the advertisement.
+    NeighborAdvert(Ipv6Address),
+}
+
+fn parse_message(packet: &[u8]) -> Option<Message> {
+    let ip_packet = Ipv6Packet::new_checked(packet).ok()?;
+    let ip_repr = Ipv6Repr::parse(&ip_packet).ok()?;
+    // RFC 4861, sections 6.1.2 and 7.1.2, the hop limit guarantees the message
+    // was not forwarded
+    if ip_repr.hop_limit != 255 {
+        return None;
+    }
+
+    let icmp_packet = Icmpv6Packet::new_checked(ip_packet.payload()).ok()?;
+    let icmp_repr = Icmpv6Repr::parse(
+        &ip_repr.src_addr.into(),
+        &ip_repr.dst_addr.into(),
+        &icmp_packet,
+        &ChecksumCapabilities::default(),
+    )
+    .ok()?;
+
+    let (router_lifetime, prefix_info) = match icmp_repr {
+        // routers advertise from their link-local address
+        Icmpv6Repr::Ndisc(NdiscRepr::RouterAdvert {
+            router_lifetime,
+            prefix_info,
+            ..
+        }) if ip_repr.src_addr.is_link_local() => (router_lifetime, prefix_info),
+        Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert { target_addr, .. }) => {
+            return Some(Message::NeighborAdvert(target_addr));
+        }
+        _ => return None,
+    };
+
+    let prefix = prefix_info
+        .filter(|info| {
+            info.flags.contains(NdiscPrefixInfoFlags::ADDRCONF)
+                && info.prefix_len == 64
+                && !info.prefix.is_link_local()
+        })
+        .map(|info| PrefixInfo {
+            prefix: info.prefix,
+            valid_lifetime: info.valid_lifetime,
+        });
+
+    Some(Message::RouterAdvert(RouterAdvert {
+        router: ip_repr.src_addr,
+        router_lifetime,
+        prefix,
+    }))
+}
+
+fn add_address(interface: &mut Interface, cidr: Ipv6Cidr) {
+    interface.update_ip_addrs(|addrs| {
+        if !addrs.contains(&IpCidr::Ipv6(cidr)) && addrs.push(IpCidr::Ipv6(cidr)).is_err() {
+            defmt::error!("No space for the IPv6 address {}", cidr);
+        }
+    });
+}
+
+fn remove_address(interface: &mut Interface, cidr: Ipv6Cidr) {
+    interface.update_ip_addrs(|addrs| addrs.retain(|addr| *addr != IpCidr::Ipv6(cidr)));
+}
@@ -0,0 +1,201 @@
+use core::{cell::Cell, convert::Infallible};
+
+use cortex_m::interrupt::{self, Mutex};
+use lilos::time::{sleep_for, with_timeout, Millis, TickTime};
+use smoltcp::wire::IpEndpoint;
+
+use crate::{
+    stack::Stack,
+    udp::{PacketMetadata, UdpSocket},
+    Error,
+};
+
+pub const NTP_PORT: u16 = 123;
+
+/// How often the time is queried once it is synchronized.
+pub const DEFAULT_INTERVAL: Millis = Millis(15 * 60 * 1000);
+/// How long to wait before querying again after a failed query.
+const RETRY_INTERVAL: Millis = Millis(10_000);
+/// The upper bound of the delay after the kiss-o'-death, which doubles the query interval.
+const MAX_KISS_OF_DEATH_INTERVAL: Millis = Millis(24 * 60 * 60 * 1000);
+/// How long to wait for the response.
+const RESPONSE_TIMEOUT: Millis = Millis(5_000);
+
+const PACKET_LEN: usize = 48;
+/// Seconds between the NTP epoch, 1900, and the Unix epoch, 1970.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Difference between the Unix time and [`TickTime`] in milliseconds,
+/// `None` until the first successful query.
+static UTC_OFFSET: Mutex<Cell<Option<u64>>> = Mutex::new(Cell::new(None));
+
+/// Returns the milliseconds since the Unix epoch, or `None` if the time was not
+/// synchronized by the [`SntpClient`] yet.
+pub fn now_utc() -> Option<u64> {
+    let offset = interrupt::free(|cs| UTC_OFFSET.borrow(cs).get())?;
+    Some(u64::from(TickTime::now()) + offset)
+}
+
+/// Timestamp of the defmt logs, milliseconds since the Unix epoch once the time is synchronized,
+/// milliseconds since boot until then.
+pub fn log_timestamp() -> u64 {
+    now_utc().unwrap_or_else(|| u64::from(TickTime::now()))
+}
+
+/// SNTP client, RFC 4330, keeping the offset used by [`now_utc`].
+pub struct SntpClient<'a> {
+    socket: UdpSocket<'a>,
+    stack: Stack<'a>,
+    server: &'a str,
+    interval: Millis,
+}
+
+impl<'a> SntpClient<'a> {
+    /// Creates a client querying the `server`, which is either an IP address,
+    /// or a hostname resolved by [`Stack::resolve`].
+    pub fn new(
+        stack: Stack<'a>,
+        server: &'a str,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer)?;
+        socket.bind(0)?;
+
+        Ok(Self {
+            socket,
+            stack,
+            server,
+            interval: DEFAULT_INTERVAL,
+        })
+    }
+
+    /// Sets how often the time is queried, [`DEFAULT_INTERVAL`] by default.
+    pub fn with_interval(mut self, interval: Millis) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Keeps querying the server, every `interval` once synchronized.
+    ///
+    /// Each kiss-o'-death response doubles the delay before the next query,
+    /// RFC 4330, section 5, a successful query resets it.
+    pub async fn run(&mut self) -> Infallible {
+        let mut refused_delay = self.interval;
+
+        loop {
+            match self.query().await {
+                Ok(offset) => {
+                    let previous =
+                        interrupt::free(|cs| UTC_OFFSET.borrow(cs).replace(Some(offset)));
+                    match previous {
+                        Some(previous) => {
+                            let step = offset as i64 - previous as i64;
+                            defmt::info!("Time synchronized, stepped by {} ms", step);
+                        }
+                        None => defmt::info!("Time synchronized"),
+                    }
+                    refused_delay = self.interval;
+                    sleep_for(self.interval).await;
+                }
+                Err(Error::Refused) => {
+                    refused_delay = Millis((refused_delay.0 * 2).min(MAX_KISS_OF_DEATH_INTERVAL.0));
+                    defmt::warn!(
+                        "SNTP server sent a kiss-o'-death, querying again in {} ms",
+                        refused_delay.0
+                    );
+                    sleep_for(refused_delay).await;
+                }
+                Err(e) => {
+                    defmt::warn!("SNTP query failed: {}", e);
+                    sleep_for(RETRY_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Queries the server and returns the new offset of the Unix time from [`TickTime`].
+    async fn query(&mut self) -> Result<u64, Error> {
+        let addresses = self.stack.resolve(self.server).await?;
+        let server = IpEndpoint::new(addresses[0], NTP_PORT);
+
+        // the transmit timestamp is returned in the originate timestamp of the response,
+        // the send time is used, so that stale responses are told apart
+        let sent_at = u64::from(TickTime::now());
+        let mut request = [0u8; PACKET_LEN];
+        // no leap second warning, version 4, client mode
+        request[0] = (4 << 3) | 3;
+        request[40..48].copy_from_slice(&sent_at.to_be_bytes());
+        self.socket.send_to(&request, server).await?;
+
+        let response = with_timeout(RESPONSE_TIMEOUT, async {
+            let mut response = [0u8; PACKET_LEN];
+            loop {
+                let (len, remote) = self.socket.recv_from(&mut response).await?;
+                if len == PACKET_LEN && remote == server && response[24..32] == request[40..48] {
+                    return Ok::<_, Error>(response);
+                }
+            }
+        })
+        .await
+        .ok_or(Error::Timeout)??;
+        let received_at = u64::from(TickTime::now());
+
+        let leap_indicator = response[0] >> 6;
+        let mode = response[0] & 0x07;
+        let stratum = response[1];
+        if mode != 4 {
+            return Err(Error::InvalidState);
+        }
+        // stratum 0 is the kiss-o'-death, the server asks not to be queried
+        if stratum == 0 {
+            return Err(Error::Refused);
+        }
+        // the alarm leap indicator, a stratum above 15 or a zero transmit timestamp
+        // mean the server is not synchronized, RFC 4330, section 5
+        if stratum > 15 || leap_indicator == 3 || response[40..48] == [0; 8] {
+            return Err(Error::InvalidState);
+        }
+
+        let server_received = ntp_to_unix_millis(&response[32..40]).ok_or(Error::InvalidState)?;
+        let server_sent = ntp_to_unix_millis(&response[40..48]).ok_or(Error::InvalidState)?;
+
+        // the network delay is assumed to be symmetric
+        let processing = server_sent.saturating_sub(server_received);
+        let round_trip = (received_at - sent_at).saturating_sub(processing);
+        let now = server_sent + round_trip / 2;
+
+        now.checked_sub(received_at).ok_or(Error::InvalidState)
+    }
+}
+
+/// Converts the 64-bit NTP timestamp to milliseconds since the Unix epoch.
+///
+/// Returns `None` for the timestamps before the Unix epoch.
+pub(crate) fn ntp_to_unix_millis(timestamp: &[u8]) -> Option<u64> {
+    let seconds = u64::from(u32::from_be_bytes([
+        timestamp[0],
+        timestamp[1],
+        timestamp[2],
+        timestamp[3],
+    ]));
+    let fraction = u64::from(u32::from_be_bytes([
+        timestamp[4],
+        timestamp[5],
+        timestamp[6],
+        timestamp[7],
+    ]));
+
+    // RFC 4330, section 3, timestamps with the most significant bit cleared
+    // are in the era starting in 2036
+    let seconds = if seconds & 0x8000_0000 == 0 {
+        seconds + (1 << 32)
+    } else {
+        seconds
+    };
+
+    let seconds = seconds.checked_sub(NTP_UNIX_OFFSET)?;
+    Some(seconds * 1000 + ((fraction * 1000) >> 32))
+}
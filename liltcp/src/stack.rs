@@ -1,31 +1,139 @@
-use core::cell::{RefCell, RefMut};
+use core::{
+    cell::{RefCell, RefMut},
+    future::poll_fn,
+    ops::RangeInclusive,
+    task::{Poll, Waker},
+};
 
-use smoltcp::iface::{Interface, SocketSet, SocketStorage};
+use lilos::exec::Notify;
+use smoltcp::{
+    iface::{Interface, MulticastError, SocketHandle, SocketSet, SocketStorage},
+    phy::Device,
+    socket::{dns, tcp, udp, AnySocket},
+    wire::{IpAddress, Ipv4Address},
+};
 
+use crate::{
+    dns::{DnsState, Snoop},
+    raw_ethernet::{self, EthernetSockets, Tap},
+    smoltcp_lilos::smol_now,
+    Error,
+};
+
+/// The maximum number of DNS servers the stack keeps track of.
+pub const MAX_DNS_SERVERS: usize = smoltcp::config::DNS_MAX_SERVER_COUNT;
+
+/// The maximum number of multicast groups the stack can be a member of.
+pub const MAX_MULTICAST_GROUPS: usize = smoltcp::config::IFACE_MAX_MULTICAST_GROUP_COUNT;
+
+/// IANA dynamic port range, used for local ports of outgoing connections.
+pub const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// The maximum number of TCP listeners on the [`EPHEMERAL_PORTS`].
+pub const MAX_EPHEMERAL_LISTENERS: usize = 4;
+
+/// The maximum number of dropped TCP sockets kept until their RST packet is sent.
+const MAX_CLOSING_SOCKETS: usize = 4;
+
+/// The maximum number of backlog sockets of all the TCP listeners together.
+pub const MAX_BACKLOG_SOCKETS: usize = 8;
+
+// ANCHOR: inner_stack
 pub struct InnerStack<'a> {
     sockets: SocketSet<'a>,
     interface: Interface,
+    socket_capacity: usize,
+    link_up: bool,
+    link_changed: Notify,
+    dns_servers: heapless::Vec<IpAddress, MAX_DNS_SERVERS>,
+    dns: DnsState,
+    // the groups to be a member of, with the number of their joins not yet left
+    multicast_groups: heapless::Vec<(Ipv4Address, usize), MAX_MULTICAST_GROUPS>,
+    joined_multicast_groups: heapless::Vec<Ipv4Address, MAX_MULTICAST_GROUPS>,
+    ethernet_sockets: EthernetSockets<'a>,
+    next_ephemeral_port: u16,
+    closing_sockets: heapless::Vec<SocketHandle, MAX_CLOSING_SOCKETS>,
+    // smoltcp doesn't expose the port of a listening socket
+    listen_ports: heapless::Vec<u16, MAX_EPHEMERAL_LISTENERS>,
+    // backlog sockets not handed over by `TcpListener::accept`, with their listening port
+    backlog_sockets: heapless::Vec<(SocketHandle, u16), MAX_BACKLOG_SOCKETS>,
+    runner_waker: Option<Waker>,
+    runner_woken: bool,
 }
 
 impl<'a> InnerStack<'a> {
-    pub fn new(storage: &'a mut [SocketStorage<'a>], interface: Interface) -> Self {
+    /// The `random_seed` picks the first local port of the outgoing connections, so
+    /// the ports used before a reboot are not reused right away, see RFC 6056.
+    /// It should come from a hardware RNG, like the [`smoltcp::iface::Config::random_seed`].
+    pub fn new(
+        storage: &'a mut [SocketStorage<'a>],
+        interface: Interface,
+        random_seed: u64,
+    ) -> Self {
         Self {
+            socket_capacity: storage.len(),
             sockets: SocketSet::new(storage),
             interface,
+            link_up: false,
+            link_changed: Notify::new(),
+            dns_servers: heapless::Vec::new(),
+            dns: DnsState::new(),
+            multicast_groups: heapless::Vec::new(),
+            joined_multicast_groups: heapless::Vec::new(),
+            ethernet_sockets: Default::default(),
+            next_ephemeral_port: seeded_ephemeral_port(random_seed),
+            closing_sockets: heapless::Vec::new(),
+            listen_ports: heapless::Vec::new(),
+            backlog_sockets: heapless::Vec::new(),
+            runner_waker: None,
+            runner_woken: false,
         }
     }
+    // ANCHOR_END: inner_stack
+
+    fn allocate_ephemeral_port(&mut self) -> Option<u16> {
+        for _ in EPHEMERAL_PORTS {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = next_ephemeral_port(port);
+
+            if !self.is_port_in_use(port) {
+                return Some(port);
+            }
+        }
+
+        None
+    }
+
+    fn free_socket_slots(&self) -> usize {
+        self.socket_capacity - self.sockets.iter().count()
+    }
+
+    fn is_port_in_use(&self, port: u16) -> bool {
+        // the raw sockets have no ports
+        self.sockets.iter().any(|(_handle, socket)| {
+            let tcp_port = tcp::Socket::downcast(socket)
+                .and_then(|socket| socket.local_endpoint())
+                .map(|endpoint| endpoint.port);
+            let udp_port = udp::Socket::downcast(socket).map(|socket| socket.endpoint().port);
+
+            tcp_port == Some(port) || udp_port == Some(port)
+        }) || self.listen_ports.contains(&port)
+    }
 }
 
+// ANCHOR: stack
 #[derive(Clone, Copy)]
 pub struct Stack<'a> {
     inner: &'a RefCell<InnerStack<'a>>,
 }
+// ANCHOR_END: stack
 
 impl<'a> Stack<'a> {
     pub fn new(inner: &'a RefCell<InnerStack<'a>>) -> Self {
         Self { inner }
     }
 
+    // ANCHOR: stack_with
     pub fn with<F, U>(&mut self, f: F) -> U
     where
         F: FnOnce((&mut SocketSet<'a>, &mut Interface)) -> U,
@@ -35,4 +143,366 @@ impl<'a> Stack<'a> {
         });
         f((&mut sockets, &mut interface))
     }
+    // ANCHOR_END: stack_with
+
+    pub(crate) fn with_dns<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce((&mut SocketSet<'a>, &mut Interface, &mut DnsState)) -> U,
+    {
+        let inner = &mut *self.inner.borrow_mut();
+        f((&mut inner.sockets, &mut inner.interface, &mut inner.dns))
+    }
+
+    pub(crate) fn with_ethernet<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce((&mut EthernetSockets<'a>, &mut Interface)) -> U,
+    {
+        let inner = &mut *self.inner.borrow_mut();
+        f((&mut inner.ethernet_sockets, &mut inner.interface))
+    }
+
+    // ANCHOR: poll
+    /// Polls the interface, this should be called by the task polling the interface
+    /// instead of [`Interface::poll`].
+    ///
+    /// Besides polling the interface, it applies the multicast group changes, passes
+    /// the frames to and from the [`raw_ethernet::EthernetSocket`]s, reads the TTLs of
+    /// the DNS responses, removes the aborted sockets that have sent their RST and puts
+    /// the closed backlog sockets of the TCP listeners back to listen.
+    pub fn poll<D: Device + ?Sized>(&mut self, device: &mut D) -> bool {
+        self.update_multicast_groups(device);
+
+        let inner = &mut *self.inner.borrow_mut();
+        let now = smol_now();
+        let frames_left =
+            raw_ethernet::transmit(&mut inner.ethernet_sockets, &inner.interface, device, now);
+
+        let mut device = Snoop::new(device, &mut inner.dns, &inner.dns_servers);
+        let mut device = Tap::new(&mut device, &mut inner.ethernet_sockets);
+        let changed = inner.interface.poll(now, &mut device, &mut inner.sockets);
+
+        remove_closed_sockets(&mut inner.sockets, &mut inner.closing_sockets);
+        listen_backlog_sockets(&mut inner.sockets, &inner.backlog_sockets);
+
+        // the rest of the raw frames is sent by the next poll, after the interface
+        // had its chance to transmit
+        if frames_left {
+            inner.runner_woken = true;
+        }
+        changed
+    }
+    // ANCHOR_END: poll
+
+    // ANCHOR: add_socket
+    /// Adds the socket to the stack, unlike [`SocketSet::add`], it doesn't panic
+    /// when the storage is full.
+    pub fn add_socket<T: AnySocket<'a>>(&mut self, socket: T) -> Result<SocketHandle, Error> {
+        let mut inner = self.inner.borrow_mut();
+        if inner.free_socket_slots() == 0 {
+            return Err(Error::NoSocketStorage);
+        }
+
+        Ok(inner.sockets.add(socket))
+    }
+    // ANCHOR_END: add_socket
+
+    pub fn free_socket_slots(&self) -> usize {
+        self.inner.borrow().free_socket_slots()
+    }
+
+    /// Keeps the ephemeral `port` of a listener from being allocated to outgoing connections,
+    /// until [`Stack::release_listen_port`] is called.
+    pub(crate) fn reserve_listen_port(&mut self, port: u16) -> Result<(), Error> {
+        if !EPHEMERAL_PORTS.contains(&port) {
+            return Ok(());
+        }
+
+        self.inner
+            .borrow_mut()
+            .listen_ports
+            .push(port)
+            .map_err(|_| Error::NoSocketStorage)
+    }
+
+    pub(crate) fn release_listen_port(&mut self, port: u16) {
+        let ports = &mut self.inner.borrow_mut().listen_ports;
+        if let Some(i) = ports.iter().position(|reserved| *reserved == port) {
+            ports.swap_remove(i);
+        }
+    }
+
+    pub(crate) fn free_backlog_slots(&self) -> usize {
+        MAX_BACKLOG_SOCKETS - self.inner.borrow().backlog_sockets.len()
+    }
+
+    /// Lets [`Stack::poll`] put the socket back to listen on the `port` whenever its
+    /// connection is closed, until [`Stack::remove_backlog_socket`] is called.
+    pub(crate) fn add_backlog_socket(
+        &mut self,
+        handle: SocketHandle,
+        port: u16,
+    ) -> Result<(), Error> {
+        self.inner
+            .borrow_mut()
+            .backlog_sockets
+            .push((handle, port))
+            .map_err(|_| Error::NoSocketStorage)?;
+        self.wake_runner();
+        Ok(())
+    }
+
+    pub(crate) fn remove_backlog_socket(&mut self, handle: SocketHandle) {
+        let sockets = &mut self.inner.borrow_mut().backlog_sockets;
+        if let Some(i) = sockets
+            .iter()
+            .position(|(backlog, _port)| *backlog == handle)
+        {
+            sockets.swap_remove(i);
+        }
+    }
+
+    /// Aborts the TCP socket and removes it from the stack.
+    ///
+    /// When the socket is connected, it is kept until the next [`Stack::poll`] sends
+    /// the RST packet to the peer, so its slot is not freed right away.
+    pub(crate) fn abort_and_remove(&mut self, handle: SocketHandle) {
+        {
+            let inner = &mut *self.inner.borrow_mut();
+            let socket = inner.sockets.get_mut::<tcp::Socket>(handle);
+            socket.abort();
+
+            if socket.remote_endpoint().is_none() {
+                inner.sockets.remove(handle);
+                return;
+            }
+            if inner.closing_sockets.push(handle).is_err() {
+                defmt::warn!("Too many closing sockets, removing without RST");
+                inner.sockets.remove(handle);
+                return;
+            }
+        }
+
+        self.wake_runner();
+    }
+
+    /// Updates the link state, this should be called by the task polling the PHY.
+    pub fn set_link_up(&mut self, up: bool) {
+        let mut inner = self.inner.borrow_mut();
+        if inner.link_up != up {
+            inner.link_up = up;
+            inner.link_changed.notify();
+        }
+    }
+
+    pub fn is_link_up(&self) -> bool {
+        self.inner.borrow().link_up
+    }
+
+    /// Wakes the `waker` on the next change of the link state.
+    pub(crate) fn subscribe_link_changes(&self, waker: &Waker) {
+        self.inner.borrow().link_changed.subscribe(waker);
+    }
+
+    /// Sets the DNS servers, usually obtained by DHCP.
+    pub fn set_dns_servers(&mut self, servers: heapless::Vec<IpAddress, MAX_DNS_SERVERS>) {
+        let inner = &mut *self.inner.borrow_mut();
+        if let Some(handle) = inner.dns.handle {
+            inner
+                .sockets
+                .get_mut::<dns::Socket>(handle)
+                .update_servers(&servers);
+        }
+        inner.dns_servers = servers;
+    }
+
+    pub fn dns_servers(&self) -> heapless::Vec<IpAddress, MAX_DNS_SERVERS> {
+        self.inner.borrow().dns_servers.clone()
+    }
+
+    /// Joins the IPv4 multicast `group`.
+    ///
+    /// The joins are counted, the stack stays a member of the group until each of them
+    /// is followed by [`Stack::leave_multicast_group`]. The membership report is sent
+    /// by the task polling the interface, see [`Stack::poll`].
+    pub fn join_multicast_group(&mut self, group: Ipv4Address) -> Result<(), Error> {
+        if !group.is_multicast() {
+            return Err(Error::Unaddressable);
+        }
+
+        {
+            let mut inner = self.inner.borrow_mut();
+            match inner
+                .multicast_groups
+                .iter_mut()
+                .find(|(joined, _)| *joined == group)
+            {
+                Some((_, count)) => *count += 1,
+                None => inner
+                    .multicast_groups
+                    .push((group, 1))
+                    .map_err(|_| Error::GroupTableFull)?,
+            }
+        }
+
+        self.wake_runner();
+        Ok(())
+    }
+
+    /// Leaves the IPv4 multicast `group` once it has been left as many times as joined.
+    pub fn leave_multicast_group(&mut self, group: Ipv4Address) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            let Some(i) = inner
+                .multicast_groups
+                .iter()
+                .position(|(joined, _)| *joined == group)
+            else {
+                return;
+            };
+
+            let count = &mut inner.multicast_groups[i].1;
+            *count -= 1;
+            if *count > 0 {
+                return;
+            }
+            inner.multicast_groups.swap_remove(i);
+        }
+
+        self.wake_runner();
+    }
+
+    /// Applies the group memberships changed by [`Stack::join_multicast_group`]
+    /// and [`Stack::leave_multicast_group`] to the interface.
+    ///
+    /// smoltcp sends the IGMP reports right away, so it needs the device.
+    fn update_multicast_groups<D: Device + ?Sized>(&mut self, device: &mut D) {
+        let inner = &mut *self.inner.borrow_mut();
+
+        for (i, group) in inner
+            .joined_multicast_groups
+            .clone()
+            .iter()
+            .enumerate()
+            .rev()
+        {
+            if inner
+                .multicast_groups
+                .iter()
+                .any(|(joined, _)| joined == group)
+            {
+                continue;
+            }
+            // the group is removed from the interface even if the leave report is not sent
+            log_multicast_error(
+                inner
+                    .interface
+                    .leave_multicast_group(device, *group, smol_now()),
+            );
+            inner.joined_multicast_groups.swap_remove(i);
+        }
+
+        for (group, _) in &inner.multicast_groups {
+            if inner.joined_multicast_groups.contains(group) {
+                continue;
+            }
+            // the group is added to the interface even if the membership report is not sent,
+            // it is then reported when the router sends a query
+            log_multicast_error(
+                inner
+                    .interface
+                    .join_multicast_group(device, *group, smol_now()),
+            );
+            let _ = inner.joined_multicast_groups.push(*group);
+        }
+    }
+
+    // ANCHOR: wake_runner
+    /// Lets the task polling the interface know that a socket has something to do,
+    /// e.g. it has new data to transmit, or it has freed space in its receive window.
+    pub fn wake_runner(&mut self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.runner_woken = true;
+        if let Some(waker) = inner.runner_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Waits until [`Stack::wake_runner`] is called.
+    ///
+    /// Only a single task, the one polling the interface, should wait for this.
+    pub async fn until_runner_woken(&mut self) {
+        poll_fn(|cx| {
+            let mut inner = self.inner.borrow_mut();
+            if core::mem::take(&mut inner.runner_woken) {
+                Poll::Ready(())
+            } else {
+                inner.runner_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+    // ANCHOR_END: wake_runner
+
+    /// Returns a port from the [`EPHEMERAL_PORTS`] range that is not used by any other socket.
+    ///
+    /// Returns `None` if all the ports are taken.
+    pub fn allocate_ephemeral_port(&mut self) -> Option<u16> {
+        self.inner.borrow_mut().allocate_ephemeral_port()
+    }
+}
+
+/// Picks a port from the [`EPHEMERAL_PORTS`] range by the `seed`.
+pub fn seeded_ephemeral_port(seed: u64) -> u16 {
+    let offset = seed % EPHEMERAL_PORTS.len() as u64;
+    // the offset is less than the length of the range
+    EPHEMERAL_PORTS.start() + offset as u16
+}
+
+/// Returns the port following `port` in the [`EPHEMERAL_PORTS`] range.
+///
+/// The ports are handed out in round-robin, so the most recently used ports,
+/// that may still be in TIME-WAIT on the remote side, are reused last.
+pub fn next_ephemeral_port(port: u16) -> u16 {
+    if port >= *EPHEMERAL_PORTS.end() || port < *EPHEMERAL_PORTS.start() {
+        *EPHEMERAL_PORTS.start()
+    } else {
+        port + 1
+    }
+}
+
+/// Removes the aborted sockets that have already sent their RST packet.
+fn remove_closed_sockets(
+    sockets: &mut SocketSet<'_>,
+    closing_sockets: &mut heapless::Vec<SocketHandle, MAX_CLOSING_SOCKETS>,
+) {
+    closing_sockets.retain(|handle| {
+        // smoltcp forgets the remote endpoint once the RST is sent
+        let sent = sockets
+            .get::<tcp::Socket>(*handle)
+            .remote_endpoint()
+            .is_none();
+        if sent {
+            sockets.remove(*handle);
+        }
+        !sent
+    });
+}
+
+/// Puts the closed backlog sockets back to listen, those still waiting for their RST
+/// to be sent are left for the next poll.
+fn listen_backlog_sockets(sockets: &mut SocketSet<'_>, backlog_sockets: &[(SocketHandle, u16)]) {
+    for (handle, port) in backlog_sockets {
+        let socket = sockets.get_mut::<tcp::Socket>(*handle);
+        if socket.state() == tcp::State::Closed && socket.remote_endpoint().is_none() {
+            // listening on a closed socket with a non-zero port cannot fail
+            let _ = socket.listen(*port);
+        }
+    }
+}
+
+fn log_multicast_error(result: Result<bool, MulticastError>) {
+    if let Err(e) = result {
+        defmt::warn!("Multicast group update failed: {}", e);
+    }
 }
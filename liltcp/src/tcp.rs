@@ -1,34 +1,66 @@
-use core::{future::poll_fn, task::Poll};
+use core::{
+    cell::Cell,
+    future::poll_fn,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    num::NonZeroU8,
+    ops::{Deref, DerefMut},
+    task::{Poll, Waker},
+};
 
+use lilos::time::{with_timeout, Millis};
 use smoltcp::{
     iface::{Context, SocketHandle},
-    socket::tcp::{self, ConnectError, RecvError, SendError},
+    socket::tcp::{self, RecvError},
     storage::RingBuffer,
-    wire::{IpEndpoint, IpListenEndpoint},
+    time::Duration,
+    wire::IpEndpoint,
 };
 
-use crate::stack::Stack;
+use crate::{stack::Stack, Error};
 
 // ANCHOR: tcp_client
 pub struct TcpClient<'a> {
-    pub stack: Stack<'a>,
-    pub handle: SocketHandle,
+    // private, as the socket behind the handle is removed from the stack on drop
+    stack: Stack<'a>,
+    handle: SocketHandle,
 }
 // ANCHOR_END: tcp_client
 
 impl<'a> TcpClient<'a> {
     // ANCHOR: tcp_new
-    pub fn new(mut stack: Stack<'a>, rx_buffer: &'a mut [u8], tx_buffer: &'a mut [u8]) -> Self {
+    pub fn new(
+        mut stack: Stack<'a>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
         let rx_buffer = RingBuffer::new(rx_buffer);
         let tx_buffer = RingBuffer::new(tx_buffer);
 
         let socket = smoltcp::socket::tcp::Socket::new(rx_buffer, tx_buffer);
-        let handle = stack.with(|(sockets, _interface)| sockets.add(socket));
+        let handle = stack.add_socket(socket)?;
 
-        Self { stack, handle }
+        Ok(Self { stack, handle })
     }
     // ANCHOR_END: tcp_new
 
+    /// Same as [`TcpClient::new`], but applies `options` to the socket.
+    pub fn with_options(
+        stack: Stack<'a>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+        options: TcpOptions,
+    ) -> Result<Self, Error> {
+        let mut client = Self::new(stack, rx_buffer, tx_buffer)?;
+        client.set_options(options);
+
+        Ok(client)
+    }
+
+    pub fn set_options(&mut self, options: TcpOptions) {
+        self.with(|socket, _context| options.apply(socket));
+    }
+
     // ANCHOR: with
     fn with<F, U>(&mut self, f: F) -> U
     where
@@ -42,20 +74,524 @@ impl<'a> TcpClient<'a> {
     }
     //ANCHOR_END: with
 
+    pub async fn connect(&mut self, remote_endpoint: impl Into<IpEndpoint>) -> Result<(), Error> {
+        self.io().connect(remote_endpoint).await
+    }
+
+    /// Same as [`TcpClient::connect`], but gives up when the connection is not established
+    /// within `timeout`.
+    ///
+    /// The socket is aborted on timeout, so it can be used for connecting again right away.
+    pub async fn connect_with_timeout(
+        &mut self,
+        remote_endpoint: impl Into<IpEndpoint>,
+        timeout: Millis,
+    ) -> Result<(), Error> {
+        self.io()
+            .connect_with_timeout(remote_endpoint, timeout)
+            .await
+    }
+
+    /// Resolves `host` with [`Stack::resolve`] and connects to the first of its addresses
+    /// that accepts the connection.
+    ///
+    /// Each address is given the `timeout`, see [`TcpClient::connect_with_timeout`].
+    /// The next address is tried only when the connection times out or is refused,
+    /// other errors are returned right away.
+    pub async fn connect_to_host(
+        &mut self,
+        host: &str,
+        port: u16,
+        timeout: Millis,
+    ) -> Result<(), Error> {
+        let addresses = self.stack.resolve(host).await?;
+
+        let mut result = Err(Error::NameNotResolved);
+        for address in addresses {
+            result = self.connect_with_timeout((address, port), timeout).await;
+            if !matches!(result, Err(Error::Timeout | Error::ConnectionReset)) {
+                break;
+            }
+            self.abort();
+        }
+        result
+    }
+
+    pub async fn send(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.io().send(buf).await
+    }
+
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.io().recv(buf).await
+    }
+
+    /// Sends the `bufs` one after another, as if they were a single contiguous buffer.
+    ///
+    /// Returns the total number of bytes sent, which may be less than the total length
+    /// of `bufs`, if the tx buffer fills up.
+    pub async fn send_vectored(&mut self, bufs: &[&[u8]]) -> Result<usize, Error> {
+        self.io().send_vectored(bufs, 0).await
+    }
+
+    /// Sends all the `bufs`, waiting for free space in the tx buffer as needed,
+    /// see [`TcpClient::send_vectored`].
+    pub async fn write_all_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), Error> {
+        self.io().write_all_vectored(bufs).await
+    }
+
+    /// Sends the whole `buf`, waiting for free space in the tx buffer as needed.
+    pub async fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.io().write_all(buf).await
+    }
+
+    /// Fills the whole `buf`, fails with [`Error::UnexpectedEof`] if the peer closes
+    /// the connection before that.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.io().read_exact(buf).await
+    }
+
+    /// Waits for data like [`TcpClient::recv`], but leaves it in the rx buffer.
+    ///
+    /// At most the rx buffer's capacity can be peeked at.
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.io().peek(buf).await
+    }
+
+    /// Waits until there is free space in the tx buffer and lets `f` write directly into it.
+    ///
+    /// `f` returns the number of bytes it has written, see [`tcp::Socket::send`].
+    pub async fn send_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        self.io().send_with(f).await
+    }
+
+    /// Waits until there is data in the rx buffer and lets `f` read directly from it.
+    ///
+    /// `f` returns the number of bytes it has consumed, see [`tcp::Socket::recv`].
+    /// On EOF, `f` is called with an empty slice.
+    pub async fn recv_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        self.io().recv_with(f).await
+    }
+
+    /// Same as [`TcpClient::send`], but fails when no data can be sent within `timeout`.
+    pub async fn send_with_timeout(&mut self, buf: &[u8], timeout: Millis) -> Result<usize, Error> {
+        with_timeout(timeout, self.send(buf))
+            .await
+            .ok_or(Error::Timeout)?
+    }
+
+    /// Same as [`TcpClient::recv`], but fails when no data arrives within `timeout`.
+    pub async fn recv_with_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Millis,
+    ) -> Result<usize, Error> {
+        with_timeout(timeout, self.recv(buf))
+            .await
+            .ok_or(Error::Timeout)?
+    }
+
+    pub fn state(&self) -> tcp::State {
+        self.io().with(|socket, _context| socket.state())
+    }
+
+    pub fn local_endpoint(&self) -> Option<IpEndpoint> {
+        self.io().with(|socket, _context| socket.local_endpoint())
+    }
+
+    pub fn remote_endpoint(&self) -> Option<IpEndpoint> {
+        self.io().with(|socket, _context| socket.remote_endpoint())
+    }
+
+    /// Waits until the socket gets to `state`.
+    ///
+    /// NOTE: smoltcp sockets can hold only a single waker per direction, so waiting for state
+    /// while another task waits in `send` or `recv` on the same socket makes one of them miss
+    /// its wake up. Use a single task for the socket, or split the client and wait for state
+    /// on the half that is not used by another task, see [`TcpReader::wait_for_state`].
+    ///
+    /// Fails with [`Error::LinkDown`] if the link goes down in the meantime.
+    pub async fn wait_for_state(&self, state: tcp::State) -> Result<(), Error> {
+        self.io()
+            .wait_until(Wakers::Both, |socket| socket.state() == state)
+            .await
+    }
+
+    /// Waits until the peer closes its side of the connection, or the connection is lost.
+    ///
+    /// See the note on waking in [`TcpClient::wait_for_state`].
+    pub async fn wait_closed(&self) -> Result<(), Error> {
+        self.io()
+            .wait_until(Wakers::Both, |socket| {
+                matches!(
+                    socket.state(),
+                    tcp::State::CloseWait
+                        | tcp::State::LastAck
+                        | tcp::State::Closing
+                        | tcp::State::TimeWait
+                        | tcp::State::Closed
+                )
+            })
+            .await
+    }
+
+    /// Returns the smoltcp's socket timeout, see [`TcpClient::set_socket_timeout`].
+    pub fn socket_timeout(&mut self) -> Option<Duration> {
+        self.with(|socket, _context| socket.timeout())
+    }
+
+    /// Sets the time after which the connection is aborted if the peer stops
+    /// acknowledging data.
+    ///
+    /// This is handled by smoltcp itself, pending operations fail with
+    /// [`Error::ConnectionReset`] once the connection is aborted.
+    /// Combine it with keep-alive to detect dead peers even when no data is being sent.
+    pub fn set_socket_timeout(&mut self, timeout: Option<Duration>) {
+        self.with(|socket, _context| socket.set_timeout(timeout));
+    }
+
+    /// Waits until all the data sent so far is acknowledged by the peer.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.io().flush().await
+    }
+
+    /// Sends FIN to the peer and waits until the peer closes its side of the connection as well.
+    ///
+    /// Fails with [`Error::LinkDown`] if the link goes down before that.
+    pub async fn close(&mut self) -> Result<(), Error> {
+        self.with(|socket, _context| socket.close());
+        self.stack.wake_runner();
+
+        self.io()
+            .wait_until(Wakers::Both, |socket| {
+                matches!(socket.state(), tcp::State::Closed | tcp::State::TimeWait)
+            })
+            .await
+    }
+
+    /// Same as [`TcpClient::close`], but gives up when the peer doesn't close its side
+    /// of the connection within `timeout`.
+    ///
+    /// The socket is aborted when closing fails, so it can be connected again right away.
+    pub async fn close_with_timeout(&mut self, timeout: Millis) -> Result<(), Error> {
+        let result = with_timeout(timeout, self.close())
+            .await
+            .unwrap_or(Err(Error::Timeout));
+
+        if result.is_err() {
+            self.abort();
+        }
+        result
+    }
+
+    /// Immediately closes the socket, a RST packet is sent to the peer on the next poll
+    /// of the stack.
+    pub fn abort(&mut self) {
+        self.io().abort();
+    }
+
+    /// Splits the client into halves that can be used from different tasks concurrently.
+    pub fn split(&mut self) -> (TcpReader<'_, 'a>, TcpWriter<'_, 'a>) {
+        (
+            TcpReader {
+                io: self.io(),
+                _client: PhantomData,
+            },
+            TcpWriter {
+                io: self.io(),
+                _client: PhantomData,
+            },
+        )
+    }
+
+    pub(crate) fn io(&self) -> TcpIo<'a> {
+        TcpIo {
+            stack: self.stack,
+            handle: self.handle,
+        }
+    }
+}
+
+/// Options of the underlying smoltcp socket.
+///
+/// The defaults are the same as smoltcp's, [`TcpOptions::new`] is equivalent to:
+///
+/// ```ignore
+/// let options = TcpOptions::new()
+///     .keep_alive(None)
+///     .nagle(true)
+///     .ack_delay(Some(Duration::from_millis(10)))
+///     .hop_limit(None)
+///     .timeout(None);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcpOptions {
+    keep_alive: Option<Duration>,
+    nagle: bool,
+    ack_delay: Option<Duration>,
+    hop_limit: Option<NonZeroU8>,
+    timeout: Option<Duration>,
+}
+
+impl TcpOptions {
+    pub const fn new() -> Self {
+        Self {
+            keep_alive: None,
+            nagle: true,
+            ack_delay: Some(Duration::from_millis(10)),
+            hop_limit: None,
+            timeout: None,
+        }
+    }
+
+    /// Interval of keep-alive packets sent when the connection is idle, see
+    /// [`tcp::Socket::set_keep_alive`].
+    pub const fn keep_alive(mut self, interval: Option<Duration>) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    /// Disabling Nagle's algorithm sends small segments right away, instead of coalescing them.
+    pub const fn nagle(mut self, enabled: bool) -> Self {
+        self.nagle = enabled;
+        self
+    }
+
+    /// Delay of the ACK packets, `None` acknowledges every segment immediately.
+    pub const fn ack_delay(mut self, delay: Option<Duration>) -> Self {
+        self.ack_delay = delay;
+        self
+    }
+
+    /// Hop limit (TTL) of the outgoing packets, `None` uses the interface's default.
+    pub const fn hop_limit(mut self, hop_limit: Option<NonZeroU8>) -> Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    /// See [`TcpClient::set_socket_timeout`].
+    pub const fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn apply(&self, socket: &mut tcp::Socket) {
+        socket.set_keep_alive(self.keep_alive);
+        socket.set_nagle_enabled(self.nagle);
+        socket.set_ack_delay(self.ack_delay);
+        socket.set_hop_limit(self.hop_limit.map(NonZeroU8::get));
+        socket.set_timeout(self.timeout);
+    }
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TcpClient<'_> {
+    fn drop(&mut self) {
+        // reset the connection, if any, and free the slot in the SocketSet,
+        // so it can be used by another socket
+        self.stack.abort_and_remove(self.handle);
+    }
+}
+
+impl TcpIo<'_> {
+    fn read_ready(&mut self) -> bool {
+        // reading at EOF returns immediately as well
+        self.with(|socket, _context| socket.can_recv() || !socket.may_recv())
+    }
+
+    fn write_ready(&mut self) -> bool {
+        // writing to a closed or reset connection fails immediately as well
+        self.with(|socket, _context| socket.can_send() || !socket.may_send())
+    }
+}
+
+impl embedded_io_async::ErrorType for TcpClient<'_> {
+    type Error = Error;
+}
+
+impl embedded_io_async::Read for TcpClient<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.recv(buf).await
+    }
+}
+
+impl embedded_io_async::Write for TcpClient<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.send(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+impl embedded_io_async::ReadReady for TcpClient<'_> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.io().read_ready())
+    }
+}
+
+impl embedded_io_async::WriteReady for TcpClient<'_> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.io().write_ready())
+    }
+}
+
+impl embedded_io_async::ErrorType for TcpReader<'_, '_> {
+    type Error = Error;
+}
+
+impl embedded_io_async::Read for TcpReader<'_, '_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.recv(buf).await
+    }
+}
+
+impl embedded_io_async::ReadReady for TcpReader<'_, '_> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.io.read_ready())
+    }
+}
+
+impl embedded_io_async::ErrorType for TcpWriter<'_, '_> {
+    type Error = Error;
+}
+
+impl embedded_io_async::Write for TcpWriter<'_, '_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.send(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+impl embedded_io_async::WriteReady for TcpWriter<'_, '_> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.io.write_ready())
+    }
+}
+
+/// Returns the non-empty parts of the `bufs` left after skipping their first `skip` bytes.
+fn skip_vectored<'b>(bufs: &'b [&'b [u8]], mut skip: usize) -> impl Iterator<Item = &'b [u8]> {
+    bufs.iter().filter_map(move |buf| {
+        if skip >= buf.len() {
+            skip -= buf.len();
+            None
+        } else {
+            let rest = &buf[skip..];
+            skip = 0;
+            Some(rest)
+        }
+    })
+}
+
+/// Passes the `bufs` without their first `skip` bytes to `send`, until it accepts only a part
+/// of one of them, and returns the number of bytes accepted.
+pub(crate) fn send_vectored_with<E>(
+    bufs: &[&[u8]],
+    skip: usize,
+    mut send: impl FnMut(&[u8]) -> Result<usize, E>,
+) -> Result<usize, E> {
+    let mut sent = 0;
+    for buf in skip_vectored(bufs, skip) {
+        let n = send(buf)?;
+        sent += n;
+        if n < buf.len() {
+            // the tx buffer is full
+            break;
+        }
+    }
+
+    Ok(sent)
+}
+
+/// The socket wakers registered by [`TcpIo::wait_until`].
+#[derive(Clone, Copy)]
+pub(crate) enum Wakers {
+    Recv,
+    Send,
+    Both,
+}
+
+/// Socket operations shared by the [`TcpClient`] and its split halves.
+#[derive(Clone, Copy)]
+pub(crate) struct TcpIo<'a> {
+    stack: Stack<'a>,
+    handle: SocketHandle,
+}
+
+impl<'a> TcpIo<'a> {
+    pub(crate) fn stack(&self) -> Stack<'a> {
+        self.stack
+    }
+
+    pub(crate) fn with<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce(&mut tcp::Socket, &mut Context) -> U,
+    {
+        self.stack.with(|(sockets, interface)| {
+            let socket = sockets.get_mut(self.handle);
+
+            f(socket, interface.context())
+        })
+    }
+
+    pub(crate) async fn connect(
+        &mut self,
+        remote_endpoint: impl Into<IpEndpoint>,
+    ) -> Result<(), Error> {
+        if !self.stack.is_link_up() {
+            return Err(Error::LinkDown);
+        }
+
+        // connecting resets the socket, so the RST of an aborted connection
+        // has to be sent by the stack first
+        self.wait_until(Wakers::Both, |socket| {
+            socket.state() != tcp::State::Closed || socket.remote_endpoint().is_none()
+        })
+        .await?;
+
+        let local_port = self
+            .stack
+            .allocate_ephemeral_port()
+            .ok_or(Error::Unaddressable)?;
+
+        let result = self.connect_from(remote_endpoint, local_port).await;
+        if result == Err(Error::LinkDown) {
+            // stop sending SYN, so the socket can be connected again
+            self.abort();
+        }
+        result
+    }
+
     // ANCHOR: connect
-    pub async fn connect(
+    async fn connect_from(
         &mut self,
         remote_endpoint: impl Into<IpEndpoint>,
-        local_endpoint: impl Into<IpListenEndpoint>,
-    ) -> Result<(), ConnectError> {
-        self.with(|socket, context| socket.connect(context, remote_endpoint, local_endpoint))?;
+        local_port: u16,
+    ) -> Result<(), Error> {
+        self.with(|socket, context| socket.connect(context, remote_endpoint, local_port))?;
+        // send SYN right away
+        self.stack.wake_runner();
 
         poll_fn(|cx| {
-            self.with(|socket, _context| {
+            let poll = self.with(|socket, _context| {
                 // shamelessly copied from embassy
                 match socket.state() {
                     tcp::State::Closed | tcp::State::TimeWait => {
-                        Poll::Ready(Err(ConnectError::InvalidState))
+                        Poll::Ready(Err(Error::ConnectionReset))
                     }
                     tcp::State::Listen => unreachable!(), // marks invalid state
                     tcp::State::SynSent | tcp::State::SynReceived => {
@@ -65,32 +601,99 @@ impl<'a> TcpClient<'a> {
                     }
                     _ => Poll::Ready(Ok(())),
                 }
-            })
+            });
+            self.fail_on_link_down(cx.waker(), poll)
         })
         .await
     }
     // ANCHOR_END: connect
 
-    // ANCHOR: send
-    pub async fn send(&mut self, buf: &[u8]) -> Result<usize, SendError> {
+    pub(crate) async fn connect_with_timeout(
+        &mut self,
+        remote_endpoint: impl Into<IpEndpoint>,
+        timeout: Millis,
+    ) -> Result<(), Error> {
+        match with_timeout(timeout, self.connect(remote_endpoint)).await {
+            Some(result) => result,
+            None => {
+                self.abort();
+                Err(Error::Timeout)
+            }
+        }
+    }
+
+    pub(crate) async fn wait_until<F>(&mut self, wakers: Wakers, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&tcp::Socket) -> bool,
+    {
         poll_fn(|cx| {
-            self.with(|socket, _context| match socket.send_slice(buf) {
+            let poll = self.with(|socket, _context| {
+                if f(socket) {
+                    return Poll::Ready(Ok(()));
+                }
+
+                // the state changes wake both wakers, so either of them is enough
+                if matches!(wakers, Wakers::Send | Wakers::Both) {
+                    socket.register_send_waker(cx.waker());
+                }
+                if matches!(wakers, Wakers::Recv | Wakers::Both) {
+                    socket.register_recv_waker(cx.waker());
+                }
+                Poll::Pending
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await
+    }
+
+    /// Fails the pending operation when the link is down, smoltcp would keep waiting
+    /// until the connection times out, which never happens without a timeout set.
+    fn fail_on_link_down<T>(
+        &self,
+        waker: &Waker,
+        poll: Poll<Result<T, Error>>,
+    ) -> Poll<Result<T, Error>> {
+        if poll.is_pending() {
+            if !self.stack.is_link_up() {
+                return Poll::Ready(Err(Error::LinkDown));
+            }
+            self.stack.subscribe_link_changes(waker);
+        }
+        poll
+    }
+
+    pub(crate) fn abort(&mut self) {
+        self.with(|socket, _context| socket.abort());
+        self.stack.wake_runner();
+    }
+
+    // ANCHOR: send
+    pub(crate) async fn send(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let result = poll_fn(|cx| {
+            let poll = self.with(|socket, _context| match socket.send_slice(buf) {
+                // there is nothing to wait for when buf is empty
+                Ok(0) if buf.is_empty() => Poll::Ready(Ok(0)),
                 Ok(0) => {
                     socket.register_send_waker(cx.waker());
                     Poll::Pending
                 }
                 Ok(n) => Poll::Ready(Ok(n)),
-                Err(e) => Poll::Ready(Err(e)),
-            })
+                Err(e) => Poll::Ready(Err(e.into())),
+            });
+            self.fail_on_link_down(cx.waker(), poll)
         })
-        .await
+        .await;
+
+        // transmit the data without waiting for the next poll of the stack
+        self.stack.wake_runner();
+        result
     }
     // ANCHOR_END: send
 
     // ANCHOR: recv
-    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, RecvError> {
-        poll_fn(|cx| {
-            self.with(|socket, _context| match socket.recv_slice(buf) {
+    pub(crate) async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let result = poll_fn(|cx| {
+            let poll = self.with(|socket, _context| match socket.recv_slice(buf) {
                 // return 0 doesn't mean EOF when buf is empty
                 Ok(0) if buf.is_empty() => Poll::Ready(Ok(0)),
                 Ok(0) => {
@@ -100,10 +703,436 @@ impl<'a> TcpClient<'a> {
                 Ok(n) => Poll::Ready(Ok(n)),
                 // EOF
                 Err(RecvError::Finished) => Poll::Ready(Ok(0)),
-                Err(RecvError::InvalidState) => Poll::Ready(Err(RecvError::InvalidState)),
-            })
+                Err(RecvError::InvalidState) => Poll::Ready(Err(Error::ConnectionReset)),
+            });
+            self.fail_on_link_down(cx.waker(), poll)
         })
-        .await
+        .await;
+
+        // let the peer know about the freed receive window
+        self.stack.wake_runner();
+        result
     }
     // ANCHOR_END: recv
+
+    /// Sends the `bufs` without their first `skip` bytes, that were sent already.
+    pub(crate) async fn send_vectored(
+        &mut self,
+        bufs: &[&[u8]],
+        skip: usize,
+    ) -> Result<usize, Error> {
+        let result = poll_fn(|cx| {
+            let poll = self.with(|socket, _context| {
+                let sent = send_vectored_with(bufs, skip, |buf| socket.send_slice(buf))?;
+
+                if sent == 0 && skip_vectored(bufs, skip).next().is_some() {
+                    socket.register_send_waker(cx.waker());
+                    return Poll::Pending;
+                }
+
+                Poll::Ready(Ok(sent))
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await;
+
+        self.stack.wake_runner();
+        result
+    }
+
+    pub(crate) async fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let n = self.send(buf).await?;
+            buf = &buf[n..];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn write_all_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), Error> {
+        let len = bufs.iter().map(|buf| buf.len()).sum();
+
+        let mut sent = 0;
+        while sent < len {
+            sent += self.send_vectored(bufs, sent).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            match self.recv(buf).await? {
+                0 => return Err(Error::UnexpectedEof),
+                n => buf = &mut buf[n..],
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn peek(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        poll_fn(|cx| {
+            let poll = self.with(|socket, _context| match socket.peek_slice(buf) {
+                Ok(0) if buf.is_empty() => Poll::Ready(Ok(0)),
+                Ok(0) => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                Ok(n) => Poll::Ready(Ok(n)),
+                // EOF
+                Err(RecvError::Finished) => Poll::Ready(Ok(0)),
+                Err(RecvError::InvalidState) => Poll::Ready(Err(Error::ConnectionReset)),
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await
+    }
+
+    pub(crate) async fn send_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        let mut f = Some(f);
+        let result = poll_fn(|cx| {
+            let poll = self.with(|socket, _context| {
+                if !socket.can_send() && socket.may_send() {
+                    socket.register_send_waker(cx.waker());
+                    return Poll::Pending;
+                }
+
+                Poll::Ready(
+                    socket
+                        .send(|buf| f.take().unwrap()(buf))
+                        .map_err(Error::from),
+                )
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await;
+
+        self.stack.wake_runner();
+        result
+    }
+
+    pub(crate) async fn recv_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        let mut f = Some(f);
+        let result = poll_fn(|cx| {
+            let poll = self.with(|socket, _context| {
+                if !socket.can_recv() && socket.may_recv() {
+                    socket.register_recv_waker(cx.waker());
+                    return Poll::Pending;
+                }
+
+                // the closure is taken only if smoltcp actually calls it,
+                // so it is still available on EOF
+                match socket.recv(|buf| f.take().unwrap()(buf)) {
+                    // EOF
+                    Err(RecvError::Finished) => Poll::Ready(Ok(f.take().unwrap()(&mut []).1)),
+                    result => Poll::Ready(result.map_err(Error::from)),
+                }
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await;
+
+        self.stack.wake_runner();
+        result
+    }
+
+    pub(crate) async fn flush(&mut self) -> Result<(), Error> {
+        poll_fn(|cx| {
+            let poll = self.with(|socket, _context| {
+                // the data is removed from the tx buffer only once it is acknowledged
+                if socket.send_queue() == 0 {
+                    Poll::Ready(Ok(()))
+                } else if !socket.is_active() {
+                    // the connection is gone, the rest of the data can never be delivered
+                    Poll::Ready(Err(Error::ConnectionReset))
+                } else {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+            });
+            self.fail_on_link_down(cx.waker(), poll)
+        })
+        .await
+    }
+}
+
+pub struct TcpReader<'c, 'a> {
+    io: TcpIo<'a>,
+    _client: PhantomData<&'c mut TcpClient<'a>>,
+}
+
+impl TcpReader<'_, '_> {
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.io.recv(buf).await
+    }
+
+    pub async fn recv_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        self.io.recv_with(f).await
+    }
+
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.io.read_exact(buf).await
+    }
+
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.io.peek(buf).await
+    }
+
+    pub fn state(&self) -> tcp::State {
+        let mut io = self.io;
+        io.with(|socket, _context| socket.state())
+    }
+
+    /// Same as [`TcpClient::wait_for_state`], but it uses only the receive waker,
+    /// so the [`TcpWriter`] can be used from another task in the meantime.
+    pub async fn wait_for_state(&mut self, state: tcp::State) -> Result<(), Error> {
+        self.io
+            .wait_until(Wakers::Recv, |socket| socket.state() == state)
+            .await
+    }
+}
+
+pub struct TcpWriter<'c, 'a> {
+    io: TcpIo<'a>,
+    _client: PhantomData<&'c mut TcpClient<'a>>,
+}
+
+impl TcpWriter<'_, '_> {
+    pub async fn send(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.io.send(buf).await
+    }
+
+    pub async fn send_with<F, R>(&mut self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut [u8]) -> (usize, R),
+    {
+        self.io.send_with(f).await
+    }
+
+    pub async fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.io.write_all(buf).await
+    }
+
+    pub async fn send_vectored(&mut self, bufs: &[&[u8]]) -> Result<usize, Error> {
+        self.io.send_vectored(bufs, 0).await
+    }
+
+    pub async fn write_all_vectored(&mut self, bufs: &[&[u8]]) -> Result<(), Error> {
+        self.io.write_all_vectored(bufs).await
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.io.flush().await
+    }
+
+    pub fn state(&self) -> tcp::State {
+        let mut io = self.io;
+        io.with(|socket, _context| socket.state())
+    }
+
+    /// Same as [`TcpClient::wait_for_state`], but it uses only the send waker,
+    /// so the [`TcpReader`] can be used from another task in the meantime.
+    pub async fn wait_for_state(&mut self, state: tcp::State) -> Result<(), Error> {
+        self.io
+            .wait_until(Wakers::Send, |socket| socket.state() == state)
+            .await
+    }
+}
+
+/// A TCP listener keeping a backlog of `N` sockets listening on the same port.
+///
+/// Having more than one socket in the `Listen` state allows the stack to accept
+/// multiple connection attempts, even when the application is busy serving another client.
+/// The sockets not handed over by [`TcpListener::accept`] are put back to listen by
+/// [`Stack::poll`] once their connection is closed.
+pub struct TcpListener<'a, const N: usize> {
+    stack: Stack<'a>,
+    port: u16,
+    handles: [SocketHandle; N],
+    in_use: [Cell<bool>; N],
+}
+
+impl<'a, const N: usize> TcpListener<'a, N> {
+    /// Fails with [`Error::NoSocketStorage`] when there is no room for `N` sockets, when
+    /// the listeners would have more than [`crate::stack::MAX_BACKLOG_SOCKETS`] sockets
+    /// together, or when the `port` is one of the [`crate::stack::EPHEMERAL_PORTS`] and there
+    /// are already [`crate::stack::MAX_EPHEMERAL_LISTENERS`] listeners on them.
+    pub fn new(
+        mut stack: Stack<'a>,
+        port: u16,
+        rx_buffers: [&'a mut [u8]; N],
+        tx_buffers: [&'a mut [u8]; N],
+    ) -> Result<Self, Error> {
+        if stack.free_socket_slots() < N || stack.free_backlog_slots() < N {
+            return Err(Error::NoSocketStorage);
+        }
+        stack.reserve_listen_port(port)?;
+
+        let mut rx_buffers = rx_buffers.into_iter();
+        let mut tx_buffers = tx_buffers.into_iter();
+
+        let handles = stack.with(|(sockets, _interface)| {
+            core::array::from_fn(|_| {
+                // both iterators are exactly N items long
+                let rx_buffer = RingBuffer::new(rx_buffers.next().unwrap());
+                let tx_buffer = RingBuffer::new(tx_buffers.next().unwrap());
+
+                sockets.add(tcp::Socket::new(rx_buffer, tx_buffer))
+            })
+        });
+
+        let listener = Self {
+            stack,
+            port,
+            handles,
+            in_use: core::array::from_fn(|_| Cell::new(false)),
+        };
+
+        for handle in listener.handles {
+            listener.with(handle, |socket| socket.listen(port))?;
+            // there is room for all the N sockets
+            let _ = stack.add_backlog_socket(handle, port);
+        }
+
+        Ok(listener)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn with<F, U>(&self, handle: SocketHandle, f: F) -> U
+    where
+        F: FnOnce(&mut tcp::Socket) -> U,
+    {
+        let mut stack = self.stack;
+        stack.with(|(sockets, _interface)| f(sockets.get_mut(handle)))
+    }
+
+    /// Waits until one of the backlog sockets gets connected and hands it over.
+    ///
+    /// The socket returns back to the backlog when the returned [`TcpConnection`] is dropped.
+    ///
+    /// NOTE: smoltcp sockets can hold only a single waker, so only one task should be waiting
+    /// in `accept` at a time.
+    pub async fn accept(&self) -> TcpConnection<'_, 'a, N> {
+        let slot = poll_fn(|cx| {
+            let mut aborted = false;
+
+            for (slot, handle) in self.handles.iter().enumerate() {
+                if self.in_use[slot].get() {
+                    continue;
+                }
+
+                let ready = self.with(*handle, |socket| match socket.state() {
+                    tcp::State::Listen | tcp::State::SynReceived => {
+                        socket.register_recv_waker(cx.waker());
+                        false
+                    }
+                    tcp::State::Established | tcp::State::CloseWait => true,
+                    // The RST is still to be sent, listening now would reset the socket
+                    // and drop it. smoltcp forgets the remote endpoint and wakes the send waker
+                    // once the RST is out.
+                    tcp::State::Closed if socket.remote_endpoint().is_some() => {
+                        socket.register_send_waker(cx.waker());
+                        false
+                    }
+                    tcp::State::Closed => {
+                        // listening on a closed socket with a non-zero port cannot fail
+                        let _ = socket.listen(self.port);
+                        socket.register_recv_waker(cx.waker());
+                        false
+                    }
+                    // the connection died before it was accepted, reset it, the stack puts
+                    // the socket back to listen once the RST is sent
+                    _ => {
+                        socket.abort();
+                        socket.register_send_waker(cx.waker());
+                        aborted = true;
+                        false
+                    }
+                });
+
+                if ready {
+                    self.in_use[slot].set(true);
+                    let mut stack = self.stack;
+                    stack.remove_backlog_socket(*handle);
+                    return Poll::Ready(slot);
+                }
+            }
+
+            if aborted {
+                let mut stack = self.stack;
+                stack.wake_runner();
+            }
+
+            Poll::Pending
+        })
+        .await;
+
+        TcpConnection {
+            listener: self,
+            slot,
+            client: ManuallyDrop::new(TcpClient {
+                stack: self.stack,
+                handle: self.handles[slot],
+            }),
+        }
+    }
+}
+
+impl<const N: usize> Drop for TcpListener<'_, N> {
+    fn drop(&mut self) {
+        for handle in self.handles {
+            self.stack.remove_backlog_socket(handle);
+            self.stack.abort_and_remove(handle);
+        }
+        self.stack.release_listen_port(self.port);
+    }
+}
+
+/// A connection accepted by a [`TcpListener`].
+///
+/// It dereferences to [`TcpClient`], so it offers the same API for sending and receiving data.
+pub struct TcpConnection<'l, 'a, const N: usize> {
+    listener: &'l TcpListener<'a, N>,
+    slot: usize,
+    // the socket belongs to the listener, so it must not be removed from the stack on drop
+    client: ManuallyDrop<TcpClient<'a>>,
+}
+
+impl<'a, const N: usize> Deref for TcpConnection<'_, 'a, N> {
+    type Target = TcpClient<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl<const N: usize> DerefMut for TcpConnection<'_, '_, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.client
+    }
+}
+
+impl<const N: usize> Drop for TcpConnection<'_, '_, N> {
+    fn drop(&mut self) {
+        // The socket is put back to the `Listen` state by the stack once it sends
+        // the RST packet, whether `accept` is awaited or not.
+        self.client.abort();
+        let mut stack = self.listener.stack;
+        // the slot was freed by `accept`, so there is room for the socket
+        let _ = stack.add_backlog_socket(self.client.handle, self.listener.port);
+        self.listener.in_use[self.slot].set(false);
+    }
 }
@@ -0,0 +1,209 @@
+use embedded_tls::{TlsConnection, TlsContext, TlsError};
+use lilos::time::{with_timeout, Millis};
+use rand_core::{CryptoRng, RngCore};
+
+use embedded_tls::{NoVerify, TlsConfig};
+
+pub use embedded_tls::{Aes128GcmSha256, TlsCipherSuite, TLS_RECORD_OVERHEAD};
+
+use crate::{
+    tcp::{TcpClient, TcpIo},
+    Error,
+};
+
+/// Size of the read record buffer that fits any record the server may send.
+pub const MAX_RECORD_LEN: usize = 16640;
+
+/// Time given to sending close_notify and to closing the TCP connection each,
+/// see [`TlsClient::close`].
+pub const CLOSE_TIMEOUT: Millis = Millis(5_000);
+
+/// TLS 1.3 client session over a [`TcpClient`], authenticated by a pre-shared key.
+///
+/// Certificate verification is not supported, the verifier of embedded-tls needs ring,
+/// which doesn't build for the target without a C toolchain and a getrandom backend.
+///
+/// NOTE: The session has no automated tests, liltcp only builds for the target, so it can't
+/// run against a server on the host. It is only checked by hand, by running the `tls_client`
+/// example against the `tls_echo` server of test-tcp-server.
+pub struct TlsClient<'a, CipherSuite = Aes128GcmSha256>
+where
+    CipherSuite: TlsCipherSuite + 'static,
+{
+    connection: TlsConnection<'a, Transport<'a>, CipherSuite>,
+    client: TcpClient<'a>,
+    /// The server sent close_notify.
+    finished: bool,
+}
+
+impl<'a, CipherSuite> TlsClient<'a, CipherSuite>
+where
+    CipherSuite: TlsCipherSuite + 'static,
+{
+    /// Wraps the connected `client`, the session is then established by
+    /// [`TlsClient::open_psk`].
+    ///
+    /// `read_record` has to fit a whole record, see [`MAX_RECORD_LEN`].
+    /// Each record sent takes [`TLS_RECORD_OVERHEAD`] bytes of `write_record` on top of the data.
+    pub fn new(
+        client: TcpClient<'a>,
+        read_record: &'a mut [u8],
+        write_record: &'a mut [u8],
+    ) -> Self {
+        Self {
+            connection: TlsConnection::new(Transport(client.io()), read_record, write_record),
+            client,
+            finished: false,
+        }
+    }
+
+    /// Performs the handshake authenticated by the pre-shared key `psk`, that the server knows
+    /// under the `identity`.
+    ///
+    /// The server certificate is not checked, the key is a part of the key schedule, so a server
+    /// not knowing it can't complete the handshake anyway.
+    ///
+    /// When the handshake fails, the session has to be closed by [`TlsClient::close`].
+    pub async fn open_psk<Rng>(
+        &mut self,
+        psk: &[u8],
+        identity: &[u8],
+        rng: &mut Rng,
+    ) -> Result<(), Error>
+    where
+        Rng: CryptoRng + RngCore,
+    {
+        let config = TlsConfig::new().with_psk(psk, &[identity]);
+        self.connection
+            .open::<Rng, NoVerify>(TlsContext::new(&config, rng))
+            .await
+            .map_err(|e| {
+                defmt::warn!("TLS handshake failed: {}", e);
+                e.into()
+            })
+    }
+
+    /// Encrypts the data into a single record and passes it to the TCP socket.
+    ///
+    /// Returns the number of bytes sent, which may be less than the length of `buf`,
+    /// if the write record buffer fills up.
+    pub async fn send(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let len = self.connection.write(buf).await?;
+        self.connection.flush().await?;
+        Ok(len)
+    }
+
+    /// Sends the whole `buf`, see [`TlsClient::send`].
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let len = self.send(buf).await?;
+            buf = &buf[len..];
+        }
+        Ok(())
+    }
+
+    /// Waits for a record and returns its decrypted data.
+    ///
+    /// Returns `Ok(0)` once the server closes the session.
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.finished {
+            return Ok(0);
+        }
+
+        match self.connection.read(buf).await {
+            Err(TlsError::ConnectionClosed) => {
+                self.finished = true;
+                Ok(0)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Waits until all the data sent so far is acknowledged by the peer.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.connection.flush().await?;
+        self.client.flush().await
+    }
+
+    /// Sends close_notify and closes the TCP connection, each step is given
+    /// [`CLOSE_TIMEOUT`].
+    ///
+    /// Returns the client, so it can be connected again for a new session.
+    /// The connection is aborted if the peer doesn't close it in time.
+    pub async fn close(self) -> TcpClient<'a> {
+        let Self {
+            connection,
+            mut client,
+            ..
+        } = self;
+
+        match with_timeout(CLOSE_TIMEOUT, connection.close()).await {
+            Some(Ok(_transport)) => {}
+            Some(Err((_transport, e))) => defmt::warn!("Sending close_notify failed: {}", e),
+            None => defmt::warn!("Sending close_notify timed out"),
+        }
+
+        if let Err(e) = client.close_with_timeout(CLOSE_TIMEOUT).await {
+            defmt::warn!("Closing the connection failed: {}", e);
+        }
+        client
+    }
+}
+
+impl<CipherSuite> embedded_io_async::ErrorType for TlsClient<'_, CipherSuite>
+where
+    CipherSuite: TlsCipherSuite + 'static,
+{
+    type Error = Error;
+}
+
+impl<CipherSuite> embedded_io_async::Read for TlsClient<'_, CipherSuite>
+where
+    CipherSuite: TlsCipherSuite + 'static,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.recv(buf).await
+    }
+}
+
+impl<CipherSuite> embedded_io_async::Write for TlsClient<'_, CipherSuite>
+where
+    CipherSuite: TlsCipherSuite + 'static,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.send(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+/// The [`TcpClient`] as seen by embedded-tls.
+///
+/// Flushing only leaves the records in the tx buffer, waiting for the ACK after every record
+/// would stall the session, [`TlsClient::flush`] waits for it explicitly.
+///
+/// The client itself is kept by the [`TlsClient`], so it outlives a session that is dropped
+/// in the middle of closing.
+struct Transport<'a>(TcpIo<'a>);
+
+impl embedded_io_async::ErrorType for Transport<'_> {
+    type Error = Error;
+}
+
+impl embedded_io_async::Read for Transport<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.recv(buf).await
+    }
+}
+
+impl embedded_io_async::Write for Transport<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.send(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use core::{future::poll_fn, num::NonZeroU8, task::Poll};
+
+use smoltcp::{
+    iface::SocketHandle,
+    socket::udp::{self, PacketBuffer, RecvError, SendError},
+    wire::{IpEndpoint, IpListenEndpoint},
+};
+
+use crate::{stack::Stack, Error};
+
+pub use smoltcp::socket::udp::PacketMetadata;
+
+pub struct UdpSocket<'a> {
+    stack: Stack<'a>,
+    handle: SocketHandle,
+}
+
+impl<'a> UdpSocket<'a> {
+    /// Creates a new UDP socket.
+    ///
+    /// The length of the metadata buffers limits the number of datagrams that can be queued,
+    /// the length of the data buffers limits their total size.
+    pub fn new(
+        mut stack: Stack<'a>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Result<Self, Error> {
+        let rx_buffer = PacketBuffer::new(rx_meta, rx_buffer);
+        let tx_buffer = PacketBuffer::new(tx_meta, tx_buffer);
+
+        let socket = udp::Socket::new(rx_buffer, tx_buffer);
+        let handle = stack.add_socket(socket)?;
+
+        Ok(Self { stack, handle })
+    }
+
+    fn with<F, U>(&mut self, f: F) -> U
+    where
+        F: FnOnce(&mut udp::Socket) -> U,
+    {
+        self.stack
+            .with(|(sockets, _interface)| f(sockets.get_mut(self.handle)))
+    }
+
+    /// Binds the socket to a local endpoint, an ephemeral port is allocated if the port is zero.
+    pub fn bind(&mut self, endpoint: impl Into<IpListenEndpoint>) -> Result<(), Error> {
+        let mut endpoint = endpoint.into();
+        if endpoint.port == 0 {
+            endpoint.port = self
+                .stack
+                .allocate_ephemeral_port()
+                .ok_or(Error::Unaddressable)?;
+        }
+
+        self.with(|socket| socket.bind(endpoint))?;
+
+        Ok(())
+    }
+
+    pub fn endpoint(&mut self) -> IpListenEndpoint {
+        self.with(|socket| socket.endpoint())
+    }
+
+    /// Sets the TTL (IPv4) or hop limit (IPv6) of the outgoing datagrams,
+    /// `None` means the default of 64.
+    pub fn set_hop_limit(&mut self, hop_limit: Option<NonZeroU8>) {
+        self.with(|socket| socket.set_hop_limit(hop_limit.map(NonZeroU8::get)))
+    }
+
+    /// Waits until there is space for the datagram in the tx buffer and queues it.
+    pub async fn send_to(
+        &mut self,
+        buf: &[u8],
+        remote_endpoint: impl Into<IpEndpoint>,
+    ) -> Result<(), Error> {
+        let remote_endpoint = remote_endpoint.into();
+
+        poll_fn(|cx| {
+            self.with(|socket| {
+                if buf.len() > socket.payload_send_capacity() {
+                    // it would never fit in
+                    return Poll::Ready(Err(Error::Truncated));
+                }
+
+                match socket.send_slice(buf, remote_endpoint) {
+                    Ok(()) => Poll::Ready(Ok(())),
+                    Err(SendError::BufferFull) => {
+                        socket.register_send_waker(cx.waker());
+                        Poll::Pending
+                    }
+                    Err(SendError::Unaddressable) => Poll::Ready(Err(Error::Unaddressable)),
+                }
+            })
+        })
+        .await?;
+
+        self.stack.wake_runner();
+        Ok(())
+    }
+
+    /// Waits for a datagram and copies it to `buf`.
+    ///
+    /// Returns the length of the datagram and the endpoint it was sent from.
+    /// If the datagram doesn't fit into `buf`, it is dropped and [`Error::Truncated`] is returned.
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, IpEndpoint), Error> {
+        poll_fn(|cx| {
+            self.with(|socket| match socket.recv_slice(buf) {
+                Ok((n, meta)) => Poll::Ready(Ok((n, meta.endpoint))),
+                Err(RecvError::Exhausted) => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                Err(RecvError::Truncated) => Poll::Ready(Err(Error::Truncated)),
+            })
+        })
+        .await
+    }
+}
+
+impl Drop for UdpSocket<'_> {
+    fn drop(&mut self) {
+        self.stack
+            .with(|(sockets, _interface)| sockets.remove(self.handle));
+    }
+}
@@ -0,0 +1,26 @@
+use std::net::{Ipv4Addr, TcpListener};
+
+use test_tcp_server::tls_echo;
+
+// Checks the `tls_client` example of liltcp, every connection it makes is reported.
+fn main() {
+    let context = tls_echo::context().unwrap();
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, tls_echo::PORT)).unwrap();
+    println!("listening on {}", listener.local_addr().unwrap());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("accepting failed: {e}");
+                continue;
+            }
+        };
+        let remote = stream.peer_addr().unwrap();
+
+        match tls_echo::check_echo(&context, stream) {
+            Ok(()) => println!("{remote}: echo ok"),
+            Err(e) => println!("{remote}: {e}"),
+        }
+    }
+}
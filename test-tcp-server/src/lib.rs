@@ -0,0 +1 @@
+pub mod tls_echo;
@@ -0,0 +1,62 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslVersion};
+
+/// The demo key of the `tls_client` example of liltcp.
+pub const PSK: [u8; 32] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+];
+pub const PSK_IDENTITY: &[u8] = b"liltcp";
+
+/// Port the `tls_client` example connects to.
+pub const PORT: u16 = 4433;
+
+/// Sent one by one, each has to come back before the next is sent.
+///
+/// The last one doesn't fit the 64 byte receive buffer of the example.
+pub const MESSAGES: [&[u8]; 3] = [b"hello", b"liltcp over TLS 1.3", &[0x5a; 200]];
+
+/// TLS 1.3 server context accepting only the [`PSK`].
+///
+/// The groups are limited to P-256, as embedded-tls fails to parse the hybrid post-quantum
+/// groups advertised by newer OpenSSL versions.
+pub fn context() -> Result<SslContext, Box<dyn Error>> {
+    let mut builder = SslContext::builder(SslMethod::tls_server())?;
+    builder.set_min_proto_version(Some(SslVersion::TLS1_3))?;
+    builder.set_ciphersuites("TLS_AES_128_GCM_SHA256")?;
+    builder.set_groups_list("P-256")?;
+    builder.set_psk_server_callback(|_ssl, identity, psk| {
+        if identity != Some(PSK_IDENTITY) {
+            return Ok(0);
+        }
+        psk[..PSK.len()].copy_from_slice(&PSK);
+        Ok(PSK.len())
+    });
+    Ok(builder.build())
+}
+
+/// Performs the handshake, checks every message of [`MESSAGES`] is echoed back and sends
+/// close_notify.
+pub fn check_echo(context: &SslContext, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut stream = Ssl::new(context)?
+        .accept(stream)
+        .map_err(|e| format!("handshake failed: {e}"))?;
+
+    for message in MESSAGES {
+        stream.write_all(message)?;
+
+        let mut echo = vec![0u8; message.len()];
+        stream.read_exact(&mut echo)?;
+        if echo != message {
+            return Err(format!("expected echo {message:02x?}, got {echo:02x?}").into());
+        }
+    }
+
+    stream.shutdown()?;
+    Ok(())
+}
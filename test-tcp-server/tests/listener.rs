@@ -0,0 +1,48 @@
+// Checks the `tcp_listener` example of liltcp running on the board.
+//
+// Flash the example and run `cargo test -- --ignored` on a host in the board's network.
+
+use std::{
+    io::{ErrorKind, Read},
+    net::TcpStream,
+    time::Duration,
+};
+
+const BOARD: &str = "10.106.0.251:8002";
+
+fn greet() {
+    let mut stream = TcpStream::connect_timeout(&BOARD.parse().unwrap(), Duration::from_secs(5))
+        .expect("the board accepts the connection");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+
+    let mut received = Vec::new();
+    let mut buffer = [0u8; 64];
+    let error = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => panic!("the connection was closed by FIN, not reset"),
+            Ok(n) => received.extend_from_slice(&buffer[..n]),
+            Err(e) => break e,
+        }
+    };
+
+    assert_eq!(error.kind(), ErrorKind::ConnectionReset);
+    // the greeting may be discarded by the OS when the RST arrives before it is read
+    assert!(b"hello\n".starts_with(&received));
+}
+
+#[test]
+#[ignore = "needs the board running the tcp_listener example"]
+fn dropped_connection_is_reset() {
+    greet();
+}
+
+#[test]
+#[ignore = "needs the board running the tcp_listener example"]
+fn backlog_listens_again_after_reset() {
+    // the listener has a single socket, so every connection reuses it
+    for _ in 0..3 {
+        greet();
+    }
+}
@@ -0,0 +1,76 @@
+// Checks the PSK TLS 1.3 echo of the `tls_client` example of liltcp.
+//
+// The board test needs the example flashed and this host at `liltcp::TLS_REMOTE_ENDPOINT`,
+// run it by `cargo test -- --ignored`. The other test runs the same check against
+// embedded-tls on the host, set up the way the example sets it up.
+//
+// liltcp's `TlsClient` itself has no automated coverage, the host test doesn't run it,
+// as liltcp only builds for the board.
+
+use std::{
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use embedded_io_adapters::std::FromStd;
+use embedded_tls::{blocking::TlsConnection, Aes128GcmSha256, NoVerify, TlsConfig, TlsContext};
+use rand_core::OsRng;
+use test_tcp_server::tls_echo::{self, PSK, PSK_IDENTITY};
+
+// the `tls_client_task` loop with the buffer sizes of the example
+fn echo_client(stream: TcpStream) {
+    let mut read_record = vec![0u8; 16640];
+    let mut write_record = vec![0u8; 1024];
+    let mut session: TlsConnection<_, Aes128GcmSha256> =
+        TlsConnection::new(FromStd::new(stream), &mut read_record, &mut write_record);
+
+    let config = TlsConfig::new().with_psk(&PSK, &[PSK_IDENTITY]);
+    session
+        .open::<_, NoVerify>(TlsContext::new(&config, &mut OsRng))
+        .expect("the handshake succeeds");
+
+    let mut buffer = [0u8; 64];
+    loop {
+        match session.read(&mut buffer) {
+            Ok(0) | Err(embedded_tls::TlsError::ConnectionClosed) => break,
+            Ok(len) => {
+                session.write(&buffer[..len]).unwrap();
+                session.flush().unwrap();
+            }
+            Err(e) => panic!("receiving failed: {e:?}"),
+        }
+    }
+
+    let _ = session.close();
+}
+
+#[test]
+fn embedded_tls_echoes() {
+    let context = tls_echo::context().unwrap();
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let client = thread::spawn(move || echo_client(TcpStream::connect(address).unwrap()));
+
+    let (stream, _remote) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    tls_echo::check_echo(&context, stream).unwrap();
+
+    client.join().unwrap();
+}
+
+#[test]
+#[ignore = "needs the board running the tls_client example"]
+fn board_echoes() {
+    let context = tls_echo::context().unwrap();
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, tls_echo::PORT)).unwrap();
+
+    let (stream, _remote) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    tls_echo::check_echo(&context, stream).unwrap();
+}